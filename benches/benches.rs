@@ -2,12 +2,16 @@
 extern crate criterion;
 extern crate generational_arena;
 
+#[allow(deprecated)]
 use criterion::{Criterion, ParameterizedBenchmark, Throughput};
 use generational_arena::{Arena, Index};
 
+// The fields only give the elements their size.
+#[allow(dead_code)]
 #[derive(Default)]
 struct Small(usize);
 
+#[allow(dead_code)]
 #[derive(Default)]
 struct Big([usize; 32]);
 
@@ -31,6 +35,7 @@ fn collect<T>(arena: &Arena<T>, n: usize) {
     }
 }
 
+#[allow(deprecated)]
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench(
         "insert",
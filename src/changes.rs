@@ -0,0 +1,165 @@
+//! Change tracking for `Arena`.
+//!
+//! See `Arena::set_track_changes` and `Arena::checkpoint` for details.
+
+use super::{BTreeSet, Index};
+
+// The set of `Changes` that an index is in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Change {
+    Added,
+    Modified,
+    Removed,
+}
+
+/// The elements added to, modified in and removed from an arena since its
+/// last checkpoint.
+///
+/// This `struct` is returned by `Arena::changes` and `Arena::checkpoint`. The
+/// three sets never overlap: an element added since the checkpoint only
+/// counts as added, however often it is modified, and one that is added and
+/// then removed does not appear at all. Indices are recorded as `Index`es,
+/// whatever the key type of the arena.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    added: BTreeSet<Index>,
+    modified: BTreeSet<Index>,
+    removed: BTreeSet<Index>,
+}
+
+impl Changes {
+    /// Get the indices of the elements added since the last checkpoint, and
+    /// still in the arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.set_track_changes(true);
+    /// let idx = arena.insert(42);
+    /// arena[idx] = 43;
+    ///
+    /// let changes = arena.changes().unwrap();
+    /// assert!(changes.added().contains(&idx));
+    /// assert!(changes.modified().is_empty());
+    /// ```
+    pub fn added(&self) -> &BTreeSet<Index> {
+        &self.added
+    }
+
+    /// Get the indices of the elements that were in the arena at the last
+    /// checkpoint, still are, and have been borrowed mutably since.
+    ///
+    /// Borrowing an element mutably is enough for it to count as modified,
+    /// whether or not it was actually changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.set_track_changes(true);
+    /// let idx = arena.insert(42);
+    /// arena.checkpoint();
+    ///
+    /// arena.get_mut(idx);
+    /// assert!(arena.changes().unwrap().modified().contains(&idx));
+    /// ```
+    pub fn modified(&self) -> &BTreeSet<Index> {
+        &self.modified
+    }
+
+    /// Get the indices of the elements that were in the arena at the last
+    /// checkpoint, and have been removed since.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.set_track_changes(true);
+    /// let idx = arena.insert(42);
+    /// arena.checkpoint();
+    ///
+    /// arena.remove(idx);
+    /// assert!(arena.changes().unwrap().removed().contains(&idx));
+    /// ```
+    pub fn removed(&self) -> &BTreeSet<Index> {
+        &self.removed
+    }
+
+    /// Returns true if no element has been added, modified or removed since
+    /// the last checkpoint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.set_track_changes(true);
+    /// assert!(arena.changes().unwrap().is_empty());
+    ///
+    /// let idx = arena.insert(42);
+    /// assert!(!arena.changes().unwrap().is_empty());
+    ///
+    /// arena.remove(idx);
+    /// assert!(arena.changes().unwrap().is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    /// Returns which of the three sets holds `i`, if any.
+    pub(crate) fn get(&self, i: Index) -> Option<Change> {
+        if self.added.contains(&i) {
+            Some(Change::Added)
+        } else if self.modified.contains(&i) {
+            Some(Change::Modified)
+        } else if self.removed.contains(&i) {
+            Some(Change::Removed)
+        } else {
+            None
+        }
+    }
+
+    /// Moves `i` to the given set, or out of all of them.
+    pub(crate) fn set(&mut self, i: Index, change: Option<Change>) {
+        self.added.remove(&i);
+        self.modified.remove(&i);
+        self.removed.remove(&i);
+        match change {
+            Some(Change::Added) => self.added.insert(i),
+            Some(Change::Modified) => self.modified.insert(i),
+            Some(Change::Removed) => self.removed.insert(i),
+            None => false,
+        };
+    }
+
+    pub(crate) fn add(&mut self, i: Index) {
+        // An element removed since the checkpoint can only come back with the
+        // same index when a snapshot or a transaction is rolled back.
+        if self.removed.remove(&i) {
+            self.modified.insert(i);
+        } else {
+            self.added.insert(i);
+        }
+    }
+
+    pub(crate) fn modify(&mut self, i: Index) {
+        if !self.added.contains(&i) {
+            self.modified.insert(i);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, i: Index) {
+        if !self.added.remove(&i) {
+            self.modified.remove(&i);
+            self.removed.insert(i);
+        }
+    }
+}
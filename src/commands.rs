@@ -0,0 +1,320 @@
+//! Deferred commands for `Arena`.
+//!
+//! See `Commands` and `Arena::apply` for details.
+
+use super::{Arena, ArenaKey, Index, ReusePolicy, Vec};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A buffer of insertions, removals and replacements to apply to an arena
+/// later, with `Arena::apply`.
+///
+/// This allows deciding what to insert into or remove from an arena while
+/// iterating over it. Insertions get their index right away: it is reserved
+/// through a `Reserver`, which only borrows the arena's reservation counter,
+/// so that it can be used alongside `Arena::iter_mut_with_reserver`.
+///
+/// Dropping a buffer without applying it does not give up the reservations
+/// of its insertions: their slots stay reserved, and are not reused, until
+/// the buffer is passed to `Commands::cancel` or the arena is cleared.
+///
+/// # Examples
+///
+/// ```
+/// use generational_arena::Arena;
+/// use generational_arena::commands::Commands;
+///
+/// let mut arena = Arena::new();
+/// let one = arena.insert(1);
+/// let two = arena.insert(2);
+///
+/// let mut commands = Commands::new();
+/// let (iter, reserver) = arena.iter_mut_with_reserver();
+/// for (idx, value) in iter {
+///     if *value % 2 == 0 {
+///         commands.remove(idx);
+///     } else {
+///         *value *= 10;
+///         commands.insert(&reserver, *value + 1);
+///     }
+/// }
+///
+/// assert!(arena.apply(commands).is_empty());
+/// assert_eq!(arena[one], 10);
+/// assert!(!arena.contains(two));
+/// assert!(arena.iter().any(|(_, &value)| value == 11));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Commands<T, K = Index> {
+    commands: Vec<Command<T, K>>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Command<T, K> {
+    Insert(K, T),
+    Remove(K),
+    Replace(K, T),
+}
+
+impl<T, K> Commands<T, K> {
+    /// Constructs a new, empty command buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let commands = Commands::<usize>::new();
+    /// assert!(commands.is_empty());
+    /// ```
+    pub fn new() -> Commands<T, K> {
+        Commands {
+            commands: Vec::new(),
+        }
+    }
+
+    /// Get the number of commands in this buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let mut commands = Commands::new();
+    /// commands.replace(idx, 43);
+    /// commands.remove(idx);
+    /// assert_eq!(commands.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns true if this buffer holds no command.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let mut commands = Commands::new();
+    /// assert!(commands.is_empty());
+    /// commands.remove(idx);
+    /// assert!(!commands.is_empty());
+    /// # let _: Commands<usize> = commands;
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Record the removal of the element at index `i`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let mut commands = Commands::new();
+    /// commands.remove(idx);
+    /// assert!(arena.contains(idx));
+    ///
+    /// arena.apply(commands);
+    /// assert!(!arena.contains(idx));
+    /// ```
+    pub fn remove(&mut self, i: K) {
+        self.commands.push(Command::Remove(i));
+    }
+
+    /// Record the replacement of the element at index `i` with `value`.
+    ///
+    /// The element keeps its index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let mut commands = Commands::new();
+    /// commands.replace(idx, 43);
+    ///
+    /// arena.apply(commands);
+    /// assert_eq!(arena[idx], 43);
+    /// ```
+    pub fn replace(&mut self, i: K, value: T) {
+        self.commands.push(Command::Replace(i, value));
+    }
+
+    pub(crate) fn into_commands(self) -> Vec<Command<T, K>> {
+        self.commands
+    }
+}
+
+impl<T, K> Commands<T, K>
+where
+    K: ArenaKey,
+{
+    /// Record the insertion of `value`, and return the index that it will
+    /// have once the commands are applied.
+    ///
+    /// The index is reserved with `reserver`, which must come from the arena
+    /// that the commands are applied to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// let mut commands = Commands::new();
+    /// let idx = commands.insert(&arena.reserver(), 42);
+    /// assert!(arena.get(idx).is_none());
+    ///
+    /// arena.apply(commands);
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    pub fn insert(&mut self, reserver: &Reserver<'_, K>, value: T) -> K {
+        self.insert_with(reserver, |_| value)
+    }
+
+    /// Record the insertion of the value returned by `create`, and return the
+    /// index that it will have once the commands are applied.
+    ///
+    /// `create` is called right away with the value's index, allowing values
+    /// that know their own index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// let mut commands = Commands::new();
+    /// let idx = commands.insert_with(&arena.reserver(), |idx| (42, idx));
+    ///
+    /// arena.apply(commands);
+    /// assert_eq!(arena[idx], (42, idx));
+    /// ```
+    pub fn insert_with(&mut self, reserver: &Reserver<'_, K>, create: impl FnOnce(K) -> T) -> K {
+        let index = reserver.reserve_index();
+        self.commands.push(Command::Insert(index, create(index)));
+        index
+    }
+
+    /// Drop the commands in this buffer without applying them, and give up
+    /// the reservations of its insertions in `arena`, so that their slots can
+    /// be reused.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::with_capacity(1);
+    ///
+    /// let mut commands = Commands::new();
+    /// let idx = commands.insert(&arena.reserver(), 42);
+    /// commands.cancel(&mut arena);
+    ///
+    /// assert_eq!(arena.fill(idx, 42), Err(42));
+    /// arena.insert(43);
+    /// arena.insert(44);
+    /// assert_eq!(arena.capacity(), 2);
+    /// ```
+    pub fn cancel<P: ReusePolicy>(self, arena: &mut Arena<T, K, P>) {
+        for command in self.commands {
+            if let Command::Insert(i, _) = command {
+                arena.cancel(i);
+            }
+        }
+    }
+}
+
+impl<T, K> Default for Commands<T, K> {
+    fn default() -> Self {
+        Commands::new()
+    }
+}
+
+/// A handle that reserves indices in an arena through a shared borrow of its
+/// reservation counter only.
+///
+/// This `struct` is created by `Arena::reserver` and
+/// `Arena::iter_mut_with_reserver`. See `Arena::reserve_index_shared` for
+/// what the reserved indices refer to.
+#[derive(Debug)]
+pub struct Reserver<'a, K = Index> {
+    reserved: &'a AtomicUsize,
+    len: usize,
+    generation: u64,
+    key: PhantomData<fn() -> K>,
+}
+
+impl<'a, K> Reserver<'a, K>
+where
+    K: ArenaKey,
+{
+    pub(crate) fn new(reserved: &'a AtomicUsize, len: usize, generation: u64) -> Self {
+        Reserver {
+            reserved,
+            len,
+            generation,
+            key: PhantomData,
+        }
+    }
+
+    /// Reserve an index in the arena that this handle comes from.
+    ///
+    /// This is the same as `Arena::reserve_index_shared`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new slot could not be addressed by the arena's index
+    /// type (see `ArenaKey::MAX_INDEX`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.reserver().reserve_index();
+    ///
+    /// assert_eq!(arena.fill(idx, 42), Ok(()));
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    pub fn reserve_index(&self) -> K {
+        let len = self.len;
+        let n = self
+            .reserved
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                // Only count the slots that the index type can address, so
+                // that `Arena::flush_reserved` never creates more.
+                match len.checked_add(n) {
+                    Some(slot) if slot <= K::MAX_INDEX => Some(n + 1),
+                    _ => None,
+                }
+            })
+            .expect("arena has no more indices available");
+        K::from_index(Index::new(len + n, self.generation))
+    }
+}
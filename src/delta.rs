@@ -0,0 +1,56 @@
+//! Deltas between `Arena`s.
+//!
+//! See `Arena::diff` and `Arena::apply_delta` for details.
+
+use super::{Entry, Index, Lifo, ReusePolicy, Vec};
+use core::marker::PhantomData;
+
+/// The differences between two arenas, which turn the first one into the
+/// second when applied to it.
+///
+/// This `struct` is created by `Arena::diff`, and applied with
+/// `Arena::apply_delta`. It holds the slots that differ between the two
+/// arenas, and the state of the second arena's free slots, which depends on
+/// its `ReusePolicy` `P`: a delta only applies to arenas with the same policy.
+/// When the "serde" feature is enabled, it can be serialized, for example to
+/// replicate an arena over the network; deserializing checks that its slots
+/// and generations fit the index type `K`, and its free slots the policy `P`.
+#[derive(Clone, Debug)]
+pub struct ArenaDelta<T, K = Index, P: ReusePolicy = Lifo> {
+    // The number of slots and elements of the old arena, which the delta
+    // applies to.
+    pub(crate) base_capacity: usize,
+    pub(crate) base_len: usize,
+    // The number of slots of the new arena.
+    pub(crate) capacity: usize,
+    // The slots that differ, in ascending order.
+    pub(crate) slots: Vec<(usize, Entry<T>)>,
+    pub(crate) generation: u64,
+    pub(crate) free_list_head: Option<usize>,
+    pub(crate) free_slots: P::FreeSlots,
+    pub(crate) quarantine: Vec<usize>,
+    pub(crate) quarantine_len: usize,
+    pub(crate) len: usize,
+    pub(crate) retired: usize,
+    pub(crate) key: PhantomData<fn() -> K>,
+}
+
+impl<T, K, P: ReusePolicy> ArenaDelta<T, K, P> {
+    /// Get the number of slots that this delta changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::with_capacity(4);
+    /// let old = arena.clone();
+    /// let idx = arena.insert(42);
+    /// arena[idx] = 43;
+    ///
+    /// assert_eq!(Arena::diff(&old, &arena).changed_slots(), 1);
+    /// ```
+    pub fn changed_slots(&self) -> usize {
+        self.slots.len()
+    }
+}
@@ -0,0 +1,353 @@
+//! The entry API for `Arena`.
+//!
+//! See `Arena::entry` for details.
+
+use super::{Arena, ArenaKey, Index, Lifo, ReusePolicy};
+use core::fmt;
+
+/// A view into a single slot of an arena, which is either occupied by the
+/// element that an index refers to, or vacant.
+///
+/// This `enum` is constructed from the `entry` method on `Arena`.
+pub enum Entry<'a, T: 'a, K: 'a = Index, P: 'a + ReusePolicy = Lifo> {
+    /// The index refers to an element in the arena.
+    Occupied(OccupiedEntry<'a, T, K, P>),
+    /// The index does not refer to an element in the arena: it is stale, or it
+    /// comes from another arena.
+    Vacant(VacantEntry<'a, T, K, P>),
+}
+
+/// A view into the element that an index refers to.
+///
+/// It is part of the `Entry` enum.
+pub struct OccupiedEntry<'a, T: 'a, K: 'a = Index, P: 'a + ReusePolicy = Lifo> {
+    arena: &'a mut Arena<T, K, P>,
+    index: K,
+}
+
+/// A view into an arena for an index that does not refer to any element.
+///
+/// It is part of the `Entry` enum.
+pub struct VacantEntry<'a, T: 'a, K: 'a = Index, P: 'a + ReusePolicy = Lifo> {
+    arena: &'a mut Arena<T, K, P>,
+    index: K,
+}
+
+impl<'a, T, K, P> Entry<'a, T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    pub(crate) fn new(arena: &'a mut Arena<T, K, P>, index: K) -> Self {
+        match arena.lookup(index.into_index()) {
+            Ok(_) => Entry::Occupied(OccupiedEntry { arena, index }),
+            Err(_) => Entry::Vacant(VacantEntry { arena, index }),
+        }
+    }
+
+    /// Get the index that this entry was created with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    /// assert_eq!(arena.entry(idx).index(), idx);
+    /// ```
+    pub fn index(&self) -> K {
+        match self {
+            Entry::Occupied(entry) => entry.index(),
+            Entry::Vacant(entry) => entry.index(),
+        }
+    }
+
+    /// Get the existing element, or insert `default` into the arena if the
+    /// index is stale.
+    ///
+    /// Returns the index of the element along with an exclusive reference to
+    /// it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    /// arena.remove(idx);
+    ///
+    /// let (new_idx, value) = arena.entry(idx).or_insert(2);
+    /// *value += 1;
+    /// assert_ne!(new_idx, idx);
+    /// assert_eq!(arena[new_idx], 3);
+    /// ```
+    pub fn or_insert(self, default: T) -> (K, &'a mut T) {
+        self.or_insert_with(|| default)
+    }
+
+    /// Get the existing element, or insert the value returned by `default`
+    /// into the arena if the index is stale.
+    ///
+    /// Returns the index of the element along with an exclusive reference to
+    /// it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    ///
+    /// let (same_idx, value) = arena.entry(idx).or_insert_with(|| 2);
+    /// assert_eq!(same_idx, idx);
+    /// assert_eq!(*value, 1);
+    /// ```
+    pub fn or_insert_with(self, default: impl FnOnce() -> T) -> (K, &'a mut T) {
+        match self {
+            Entry::Occupied(entry) => (entry.index, entry.into_mut()),
+            Entry::Vacant(entry) => entry.arena.insert_with_mut(|_| default()),
+        }
+    }
+
+    /// Modify the existing element, if any, before any potential insertion
+    /// into the arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    ///
+    /// arena.entry(idx).and_modify(|v| *v += 1).or_insert(0);
+    /// assert_eq!(arena[idx], 2);
+    /// ```
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, T, K, P> OccupiedEntry<'a, T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    /// Get the index of the element in this entry.
+    pub fn index(&self) -> K {
+        self.index
+    }
+
+    /// Get a shared reference to the element in this entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// if let Entry::Occupied(entry) = arena.entry(idx) {
+    ///     assert_eq!(entry.get(), &42);
+    /// }
+    /// ```
+    pub fn get(&self) -> &T {
+        self.arena.occupied(self.index.into_index())
+    }
+
+    /// Get an exclusive reference to the element in this entry.
+    ///
+    /// To get a reference that outlives the entry, use `into_mut`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// if let Entry::Occupied(mut entry) = arena.entry(idx) {
+    ///     *entry.get_mut() += 1;
+    /// }
+    /// assert_eq!(arena[idx], 43);
+    /// ```
+    pub fn get_mut(&mut self) -> &mut T {
+        self.arena.occupied_mut(self.index.into_index())
+    }
+
+    /// Convert this entry into an exclusive reference to its element, with the
+    /// lifetime of the borrow of the arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// if let Entry::Occupied(entry) = arena.entry(idx) {
+    ///     *entry.into_mut() += 1;
+    /// }
+    /// assert_eq!(arena[idx], 43);
+    /// ```
+    pub fn into_mut(self) -> &'a mut T {
+        self.arena.occupied_mut(self.index.into_index())
+    }
+
+    /// Remove the element in this entry from the arena, and return it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// if let Entry::Occupied(entry) = arena.entry(idx) {
+    ///     assert_eq!(entry.remove(), 42);
+    /// }
+    /// assert!(!arena.contains(idx));
+    /// ```
+    pub fn remove(self) -> T {
+        self.arena.remove_occupied(self.index.into_index())
+    }
+
+    /// Replace the element in this entry with `value`, and return the old
+    /// element.
+    ///
+    /// The element's index stays the same.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// if let Entry::Occupied(mut entry) = arena.entry(idx) {
+    ///     assert_eq!(entry.replace(43), 42);
+    /// }
+    /// assert_eq!(arena[idx], 43);
+    /// ```
+    pub fn replace(&mut self, value: T) -> T {
+        core::mem::replace(self.get_mut(), value)
+    }
+}
+
+impl<'a, T, K, P> VacantEntry<'a, T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    /// Get the index that this entry was created with.
+    ///
+    /// This index does not refer to any element in the arena.
+    pub fn index(&self) -> K {
+        self.index
+    }
+
+    /// Insert `value` into the arena, allocating more capacity if necessary,
+    /// and return its new index.
+    ///
+    /// The new index is never equal to the stale index that this entry was
+    /// created with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    /// arena.remove(idx);
+    ///
+    /// if let Entry::Vacant(entry) = arena.entry(idx) {
+    ///     let new_idx = entry.insert(43);
+    ///     assert_eq!(arena[new_idx], 43);
+    /// }
+    /// ```
+    pub fn insert(self, value: T) -> K {
+        self.arena.insert(value)
+    }
+
+    /// Insert the value returned by `create` into the arena, allocating more
+    /// capacity if necessary, and return its new index.
+    ///
+    /// `create` is called with the new value's associated index, allowing
+    /// values that know their own index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert((0, None));
+    /// arena.remove(idx);
+    ///
+    /// if let Entry::Vacant(entry) = arena.entry(idx) {
+    ///     let new_idx = entry.insert_with(|new_idx| (1, Some(new_idx)));
+    ///     assert_eq!(arena[new_idx], (1, Some(new_idx)));
+    /// }
+    /// ```
+    pub fn insert_with(self, create: impl FnOnce(K) -> T) -> K {
+        self.arena.insert_with(create)
+    }
+}
+
+impl<'a, T, K, P> fmt::Debug for Entry<'a, T, K, P>
+where
+    T: fmt::Debug,
+    K: ArenaKey + fmt::Debug,
+    P: ReusePolicy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Entry::Occupied(entry) => f.debug_tuple("Entry").field(entry).finish(),
+            Entry::Vacant(entry) => f.debug_tuple("Entry").field(entry).finish(),
+        }
+    }
+}
+
+impl<'a, T, K, P> fmt::Debug for OccupiedEntry<'a, T, K, P>
+where
+    T: fmt::Debug,
+    K: ArenaKey + fmt::Debug,
+    P: ReusePolicy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("index", &self.index)
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl<'a, T, K, P: ReusePolicy> fmt::Debug for VacantEntry<'a, T, K, P>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VacantEntry")
+            .field("index", &self.index)
+            .finish()
+    }
+}
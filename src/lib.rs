@@ -52,13 +52,14 @@ longer need them, because we end up either
     * `obj1` attempts to get `obj2` at index `i`, but incorrectly is given
       `obj3`, when instead the get should fail.
 
-By introducing a monotonically increasing generation counter to the collection,
-associating each element in the collection with the generation when it was
-inserted, and getting elements from the collection with the *pair* of index and
-the generation at the time when the element was inserted, then we can solve the
-aforementioned ABA problem. When indexing into the collection, if the index
-pair's generation does not match the generation of the element at that index,
-then the operation fails.
+By giving each slot of the collection a monotonically increasing generation
+counter that is bumped whenever the slot's element is removed, associating each
+element in the collection with its slot's generation when it was inserted, and
+getting elements from the collection with the *pair* of index and the generation
+at the time when the element was inserted, then we can solve the aforementioned
+ABA problem. When indexing into the collection, if the index pair's generation
+does not match the generation of the element at that index, then the operation
+fails.
 
 ## Features
 
@@ -146,18 +147,45 @@ generational-arena = { version = "0.2", features = ["serde"] }
 cfg_if::cfg_if! {
     if #[cfg(feature = "std")] {
         extern crate std;
+        use std::boxed::Box;
+        use std::collections::{self, BTreeSet, BinaryHeap, VecDeque};
+        use std::sync::Arc;
         use std::vec::{self, Vec};
     } else {
         extern crate alloc;
+        use alloc::boxed::Box;
+        use alloc::collections::{self, BTreeSet, BinaryHeap, VecDeque};
+        use alloc::sync::Arc;
         use alloc::vec::{self, Vec};
     }
 }
 
 use core::cmp;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 use core::iter::{self, Extend, FromIterator, FusedIterator};
+use core::marker::PhantomData;
 use core::mem;
+use core::num::{NonZeroU32, NonZeroU64};
 use core::ops;
 use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub mod changes;
+pub mod commands;
+pub mod delta;
+pub mod entry;
+pub mod observed;
+pub mod persistent;
+pub mod snapshot;
+pub mod transaction;
+mod trie;
+
+use changes::Changes;
+use commands::{Command, Commands, Reserver};
+use delta::ArenaDelta;
+use snapshot::{ArenaSnapshot, Tracker};
+use transaction::{Journal, Transaction, Undo};
 
 #[cfg(feature = "serde")]
 mod serde_impl;
@@ -165,22 +193,83 @@ mod serde_impl;
 /// The `Arena` allows inserting and removing elements that are referred to by
 /// `Index`.
 ///
+/// The second type parameter, `K`, is the type of the indices that the arena
+/// hands out and accepts. It defaults to the untyped `Index`, but can be set to
+/// `TypedIndex<T>` (see `TypedArena`) so that indices from arenas of different
+/// element types cannot be mixed up, or to any other type implementing
+/// `ArenaKey`, such as those declared with `new_key_type!`.
+///
+/// The third type parameter, `P`, is the `ReusePolicy` that decides which free
+/// slot is reused next. It defaults to `Lifo`, which reuses the most recently
+/// freed slot.
+///
 /// [See the module-level documentation for example usage and motivation.](./index.html)
 #[derive(Debug)]
-pub struct Arena<T> {
+pub struct Arena<T, K = Index, P: ReusePolicy = Lifo> {
     items: Vec<Entry<T>>,
+    // The generation that newly created slots start at. It is never lower than
+    // the next generation of any slot that has been dropped from `items`, so
+    // that stale indices into dropped slots cannot match new elements, and
+    // never higher than `K::MAX_GENERATION`, because slots whose generations
+    // have run out are retired in place rather than dropped.
     generation: u64,
+    // The free list is linked through the free slots for `Lifo` and `Fifo`,
+    // starting at `free_list_head`. `free_slots` holds the tail of the list
+    // for `Fifo`, and a heap of the free slots for `LowestFirst`.
     free_list_head: Option<usize>,
+    free_slots: P::FreeSlots,
+    // Free slots that are not on the free list yet, oldest first. See
+    // `set_quarantine_len`.
+    quarantine: VecDeque<usize>,
+    quarantine_len: usize,
+    // The number of slots past the end of `items` whose indices have been
+    // handed out by `reserve_index_shared`. See `flush_reserved`.
+    reserved: AtomicUsize,
+    // The changes made by the ongoing transaction, if any, which only lends
+    // its journal to the arena while one of its methods runs, so that a
+    // forgotten transaction leaves none behind. See `begin`.
+    journal: Option<Box<Journal<T>>>,
+    // The slots as of the last snapshot taken or restored, and the slots
+    // changed since, if they are still tracked. See `restore`.
+    tracker: Option<Box<Tracker<T>>>,
+    // The elements added, modified and removed since the last checkpoint, if
+    // changes are tracked. See `set_track_changes`.
+    changes: Option<Box<Changes>>,
     len: usize,
+    retired: usize,
+    key: PhantomData<fn() -> K>,
+    policy: PhantomData<fn() -> P>,
 }
 
-impl<T: Clone> Clone for Arena<T> {
+/// An `Arena` whose indices are typed by the arena's element type.
+///
+/// See `TypedIndex` for details.
+pub type TypedArena<T> = Arena<T, TypedIndex<T>>;
+
+/// An `Arena` that uses 8-byte `CompactIndex`es.
+///
+/// See `CompactIndex` for details.
+pub type CompactArena<T> = Arena<T, CompactIndex>;
+
+impl<T: Clone, K, P: ReusePolicy> Clone for Arena<T, K, P> {
     fn clone(&self) -> Self {
         Self {
             items: self.items.clone(),
             generation: self.generation,
             free_list_head: self.free_list_head,
-            len: self.len
+            free_slots: self.free_slots.clone(),
+            quarantine: self.quarantine.clone(),
+            quarantine_len: self.quarantine_len,
+            // Pending shared reservations were handed out by this arena, so
+            // they cannot be filled in the copy.
+            reserved: AtomicUsize::new(0),
+            journal: None,
+            tracker: None,
+            changes: self.changes.clone(),
+            len: self.len,
+            retired: self.retired,
+            key: PhantomData,
+            policy: PhantomData,
         }
     }
 
@@ -188,24 +277,46 @@ impl<T: Clone> Clone for Arena<T> {
         self.items.clone_from(&other.items);
         self.generation = other.generation;
         self.free_list_head = other.free_list_head;
+        self.free_slots.clone_from(&other.free_slots);
+        self.quarantine.clone_from(&other.quarantine);
+        self.quarantine_len = other.quarantine_len;
+        *self.reserved.get_mut() = 0;
         self.len = other.len;
+        self.retired = other.retired;
+        self.tracker = None;
+        self.changes.clone_from(&other.changes);
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum Entry<T> {
-    Free { next_free: Option<usize> },
+    // `generation` is the generation that the next element inserted into this
+    // slot will get.
+    Free { next_free: Option<usize>, generation: u64 },
     Occupied { generation: u64, value: T },
+    // A slot whose index has been handed out by `reserve_index`, and that is
+    // waiting for its value.
+    Reserved { generation: u64 },
+    // A slot whose generation has reached the arena's key type's maximum. It
+    // is never put back on the free list.
+    Retired,
 }
 
 impl<T: Clone> Clone for Entry<T> {
     fn clone(&self) -> Self {
         match self {
-            Entry::Free { next_free } => Entry::Free { next_free: *next_free },
+            Entry::Free { next_free, generation } => Entry::Free {
+                next_free: *next_free,
+                generation: *generation,
+            },
             Entry::Occupied { generation, value } => Entry::Occupied {
                 generation: *generation,
                 value: value.clone(),
-            }
+            },
+            Entry::Reserved { generation } => Entry::Reserved {
+                generation: *generation,
+            },
+            Entry::Retired => Entry::Retired,
         }
     }
 
@@ -237,25 +348,48 @@ impl<T: Clone> Clone for Entry<T> {
 /// let idx = arena.insert(123);
 /// assert_eq!(arena[idx], 123);
 /// ```
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+///
+/// `Option<Index>` is the same size as `Index`:
+///
+/// ```
+/// use generational_arena::Index;
+/// use std::mem::size_of;
+///
+/// assert_eq!(size_of::<Option<Index>>(), size_of::<Index>());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Index {
     index: usize,
-    generation: u64,
+    // The generation plus one, so that `Option<Index>` can use the zero niche.
+    // As a consequence, `u64::MAX` is not a valid generation.
+    generation: NonZeroU64,
 }
 
 impl Index {
+    #[inline]
+    fn new(index: usize, generation: u64) -> Index {
+        Index {
+            index,
+            generation: NonZeroU64::new(generation.wrapping_add(1))
+                .expect("`u64::MAX` is not a valid generation"),
+        }
+    }
+
+    #[inline]
+    fn generation(self) -> u64 {
+        self.generation.get() - 1
+    }
+
     /// Create a new `Index` from its raw parts.
     ///
     /// The parts must have been returned from an earlier call to
     /// `into_raw_parts`.
     ///
     /// Providing arbitrary values will lead to malformed indices and ultimately
-    /// panics.
+    /// panics. In particular, `u64::MAX` is never a valid generation, and
+    /// passing it as `b` panics immediately.
     pub fn from_raw_parts(a: usize, b: u64) -> Index {
-        Index {
-            index: a,
-            generation: b,
-        }
+        Index::new(a, b)
     }
 
     /// Convert this `Index` into its raw parts.
@@ -266,118 +400,1021 @@ impl Index {
     /// types whose definition you can't customize, but which you can construct
     /// instances of, this method can be useful.
     pub fn into_raw_parts(self) -> (usize, u64) {
-        (self.index, self.generation)
+        (self.index, self.generation())
     }
 }
 
-const DEFAULT_CAPACITY: usize = 4;
-
-impl<T> Default for Arena<T> {
-    fn default() -> Arena<T> {
-        Arena::new()
+impl fmt::Debug for Index {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Index")
+            .field("index", &self.index)
+            .field("generation", &self.generation())
+            .finish()
     }
 }
 
-impl<T> Arena<T> {
-    /// Constructs a new, empty `Arena`.
-    ///
-    /// # Examples
+/// An `Index` that can only be used with arenas holding elements of type `T`.
+///
+/// Using a `TypedIndex<T>` as the key type of an arena (see `TypedArena`) makes
+/// it a compile-time error to use an index returned by an arena of one element
+/// type with an arena of another element type. `TypedIndex<T>` is `Copy`,
+/// `Eq`, `Ord` and `Hash` regardless of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use generational_arena::{Index, TypedArena, TypedIndex};
+///
+/// struct Player(&'static str);
+///
+/// let mut players = TypedArena::with_key();
+/// let idx: TypedIndex<Player> = players.insert(Player("Ferris"));
+/// assert_eq!(players[idx].0, "Ferris");
+///
+/// // Typed indices can be converted to and from untyped ones.
+/// let raw: Index = idx.into();
+/// assert_eq!(TypedIndex::<Player>::from(raw), idx);
+/// ```
+///
+/// Indices for one element type are rejected by arenas of another:
+///
+/// ```compile_fail
+/// use generational_arena::TypedArena;
+///
+/// struct Player;
+/// struct Bullet;
+///
+/// let mut players = TypedArena::with_key();
+/// let bullets = TypedArena::<Bullet>::with_key();
+/// let idx = players.insert(Player);
+/// bullets.get(idx);
+/// ```
+pub struct TypedIndex<T> {
+    index: Index,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedIndex<T> {
+    /// Create a new `TypedIndex` from its raw parts.
     ///
-    /// ```
-    /// use generational_arena::Arena;
+    /// The parts must have been returned from an earlier call to
+    /// `into_raw_parts`.
     ///
-    /// let mut arena = Arena::<usize>::new();
-    /// # let _ = arena;
-    /// ```
-    pub fn new() -> Arena<T> {
-        Arena::with_capacity(DEFAULT_CAPACITY)
+    /// Providing arbitrary values will lead to malformed indices and ultimately
+    /// panics.
+    pub fn from_raw_parts(a: usize, b: u64) -> TypedIndex<T> {
+        Index::from_raw_parts(a, b).into()
     }
 
-    /// Constructs a new, empty `Arena<T>` with the specified capacity.
-    ///
-    /// The `Arena<T>` will be able to hold `n` elements without further allocation.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use generational_arena::Arena;
-    ///
-    /// let mut arena = Arena::with_capacity(10);
-    ///
-    /// // These insertions will not require further allocation.
-    /// for i in 0..10 {
-    ///     assert!(arena.try_insert(i).is_ok());
-    /// }
+    /// Convert this `TypedIndex` into its raw parts.
     ///
-    /// // But now we are at capacity, and there is no more room.
-    /// assert!(arena.try_insert(99).is_err());
-    /// ```
-    pub fn with_capacity(n: usize) -> Arena<T> {
-        let n = cmp::max(n, 1);
-        let mut arena = Arena {
-            items: Vec::new(),
-            generation: 0,
-            free_list_head: None,
-            len: 0,
-        };
-        arena.reserve(n);
-        arena
+    /// See `Index::into_raw_parts` for details.
+    pub fn into_raw_parts(self) -> (usize, u64) {
+        self.index.into_raw_parts()
+    }
+}
+
+impl<T> From<Index> for TypedIndex<T> {
+    fn from(index: Index) -> TypedIndex<T> {
+        TypedIndex {
+            index,
+            marker: PhantomData,
+        }
     }
+}
 
-    /// Clear all the items inside the arena, but keep its allocation.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use generational_arena::Arena;
-    ///
-    /// let mut arena = Arena::with_capacity(1);
-    /// arena.insert(42);
-    /// arena.insert(43);
+impl<T> From<TypedIndex<T>> for Index {
+    fn from(index: TypedIndex<T>) -> Index {
+        index.index
+    }
+}
+
+/// A type that can be used to index into an `Arena`.
+///
+/// Keys are thin wrappers around an `Index`, and the `Arena<T, K>` that uses
+/// them yields and accepts `K` everywhere it would otherwise use `Index`. This
+/// lets you use your own newtypes as arena indices without unwrapping them at
+/// every call site.
+///
+/// Rather than implementing this trait by hand, you will usually want to use
+/// the `new_key_type!` macro.
+///
+/// # Examples
+///
+/// ```
+/// use generational_arena::{Arena, ArenaKey, Index};
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// struct NodeId(Index);
+///
+/// impl ArenaKey for NodeId {
+///     fn from_index(index: Index) -> Self {
+///         NodeId(index)
+///     }
+///
+///     fn into_index(self) -> Index {
+///         self.0
+///     }
+/// }
+///
+/// let mut nodes = Arena::<&str, NodeId>::with_key();
+/// let id: NodeId = nodes.insert("root");
+/// assert_eq!(nodes[id], "root");
+/// ```
+pub trait ArenaKey: Copy {
+    /// The largest slot index that this key type can represent.
     ///
-    /// arena.clear();
+    /// An arena using this key type never grows beyond `MAX_INDEX + 1` slots.
+    const MAX_INDEX: usize = usize::MAX;
+
+    /// The largest generation that this key type can represent.
     ///
-    /// assert_eq!(arena.capacity(), 2);
-    /// ```
-    pub fn clear(&mut self) {
-        self.items.clear();
+    /// An arena using this key type never hands out a generation larger than
+    /// `MAX_GENERATION`. The default is the largest generation that an `Index`
+    /// can represent, `u64::MAX - 1`.
+    const MAX_GENERATION: u64 = u64::MAX - 1;
 
-        let end = self.items.capacity();
-        self.items.extend((0..end).map(|i| {
-            if i == end - 1 {
-                Entry::Free { next_free: None }
-            } else {
-                Entry::Free {
-                    next_free: Some(i + 1),
-                }
+    /// Create a key from an untyped `Index`.
+    fn from_index(index: Index) -> Self;
+
+    /// Convert this key into an untyped `Index`.
+    fn into_index(self) -> Index;
+}
+
+impl ArenaKey for Index {
+    #[inline]
+    fn from_index(index: Index) -> Self {
+        index
+    }
+
+    #[inline]
+    fn into_index(self) -> Index {
+        self
+    }
+}
+
+impl<T> ArenaKey for TypedIndex<T> {
+    #[inline]
+    fn from_index(index: Index) -> Self {
+        index.into()
+    }
+
+    #[inline]
+    fn into_index(self) -> Index {
+        self.index
+    }
+}
+
+impl ArenaKey for CompactIndex {
+    const MAX_INDEX: usize = u32::MAX as usize;
+    const MAX_GENERATION: u64 = u32::MAX as u64 - 1;
+
+    /// Panics if `index` does not fit in a `CompactIndex`.
+    #[inline]
+    fn from_index(index: Index) -> Self {
+        assert!(
+            index.index <= Self::MAX_INDEX && index.generation() <= Self::MAX_GENERATION,
+            "index does not fit in a `CompactIndex`"
+        );
+        CompactIndex::from_raw_parts(index.index as u32, index.generation() as u32)
+    }
+
+    #[inline]
+    fn into_index(self) -> Index {
+        Index::new(self.index as usize, u64::from(self.generation()))
+    }
+}
+
+/// Declare new key types for use with `Arena`.
+///
+/// Each declared type is a newtype around `Index` that implements `ArenaKey`,
+/// `Clone`, `Copy`, `Debug`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, `Hash` and
+/// conversions from and into `Index`. When the "serde" feature is enabled, it
+/// also implements `Serialize` and `Deserialize` using the same format as
+/// `Index`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate generational_arena;
+/// use generational_arena::Arena;
+///
+/// new_key_type! {
+///     /// The index of a node in a graph.
+///     pub struct NodeId;
+///
+///     /// The index of an edge in a graph.
+///     struct EdgeId;
+/// }
+///
+/// # fn main() {
+/// let mut nodes = Arena::<&str, NodeId>::with_key();
+/// let mut edges = Arena::<(NodeId, NodeId), EdgeId>::with_key();
+///
+/// let a = nodes.insert("a");
+/// let b = nodes.insert("b");
+/// let ab = edges.insert((a, b));
+///
+/// let (from, to) = edges[ab];
+/// assert_eq!(nodes[from], "a");
+/// assert_eq!(nodes[to], "b");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! new_key_type {
+    ( $(#[$outer:meta])* $vis:vis struct $name:ident; $($rest:tt)* ) => {
+        $(#[$outer])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[repr(transparent)]
+        $vis struct $name($crate::Index);
+
+        impl $crate::ArenaKey for $name {
+            #[inline]
+            fn from_index(index: $crate::Index) -> Self {
+                $name(index)
+            }
+
+            #[inline]
+            fn into_index(self) -> $crate::Index {
+                self.0
             }
-        }));
-        if !self.is_empty() {
-            // Increment generation, but if there are no elements, do nothing to
-            // avoid unnecessary incrementing generation.
-            self.generation += 1;
         }
-        self.free_list_head = Some(0);
-        self.len = 0;
+
+        impl ::core::convert::From<$crate::Index> for $name {
+            fn from(index: $crate::Index) -> Self {
+                $name(index)
+            }
+        }
+
+        impl ::core::convert::From<$name> for $crate::Index {
+            fn from(key: $name) -> Self {
+                key.0
+            }
+        }
+
+        $crate::__serialize_key!($name);
+
+        $crate::new_key_type!($($rest)*);
+    };
+
+    () => {}
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub use serde as __serde;
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serialize_key {
+    ( $name:ty ) => {
+        impl $crate::__serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+            where
+                S: $crate::__serde::Serializer,
+            {
+                $crate::__serde::Serialize::serialize(&self.0, serializer)
+            }
+        }
+
+        impl<'de> $crate::__serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+            where
+                D: $crate::__serde::Deserializer<'de>,
+            {
+                $crate::__serde::Deserialize::deserialize(deserializer).map($crate::ArenaKey::from_index)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serialize_key {
+    ( $name:ty ) => {};
+}
+
+impl<T> Clone for TypedIndex<T> {
+    fn clone(&self) -> Self {
+        *self
     }
+}
 
-    /// Attempts to insert `value` into the arena using existing capacity.
-    ///
-    /// This method will never allocate new capacity in the arena.
-    ///
-    /// If insertion succeeds, then the `value`'s index is returned. If
-    /// insertion fails, then `Err(value)` is returned to give ownership of
-    /// `value` back to the caller.
-    ///
-    /// # Examples
+impl<T> Copy for TypedIndex<T> {}
+
+impl<T> fmt::Debug for TypedIndex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedIndex").field(&self.index).finish()
+    }
+}
+
+impl<T> PartialEq for TypedIndex<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for TypedIndex<T> {}
+
+impl<T> PartialOrd for TypedIndex<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for TypedIndex<T> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+impl<T> Hash for TypedIndex<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+/// A compact index (and generation) into an `Arena`, made of a `u32` slot
+/// index and a `u32` generation.
+///
+/// A `CompactIndex` is 8 bytes large, half the size of an `Index`. In exchange,
+/// an arena using `CompactIndex` as its key type (see `CompactArena`) has the
+/// following limits:
+///
+/// * It can hold at most `2^32` slots. Growing the arena beyond that panics,
+///   and `try_insert` returns `Err` once all the slots are occupied.
+///
+/// * A slot's generation cannot exceed `u32::MAX - 1`. When an element with
+///   that generation is removed, its slot is retired rather than wrapping the
+///   generation around and making stale indices valid again. See
+///   `Arena::retired_count`.
+///
+/// Like `Index`, `CompactIndex` has a niche, so `Option<CompactIndex>` is also 8
+/// bytes large.
+///
+/// # Examples
+///
+/// ```
+/// use generational_arena::{CompactArena, CompactIndex};
+///
+/// let mut arena = CompactArena::with_key();
+/// let idx: CompactIndex = arena.insert(123);
+/// assert_eq!(arena[idx], 123);
+/// assert_eq!(std::mem::size_of::<CompactIndex>(), 8);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactIndex {
+    index: u32,
+    // The generation plus one, like in `Index`.
+    generation: NonZeroU32,
+}
+
+impl CompactIndex {
+    #[inline]
+    fn generation(self) -> u32 {
+        self.generation.get() - 1
+    }
+
+    /// Create a new `CompactIndex` from its raw parts.
     ///
-    /// ```
-    /// use generational_arena::Arena;
+    /// The parts must have been returned from an earlier call to
+    /// `into_raw_parts`.
     ///
-    /// let mut arena = Arena::new();
+    /// Providing arbitrary values will lead to malformed indices and ultimately
+    /// panics. In particular, `u32::MAX` is never a valid generation, and
+    /// passing it as `b` panics immediately.
+    pub fn from_raw_parts(a: u32, b: u32) -> CompactIndex {
+        CompactIndex {
+            index: a,
+            generation: NonZeroU32::new(b.wrapping_add(1))
+                .expect("`u32::MAX` is not a valid generation"),
+        }
+    }
+
+    /// Convert this `CompactIndex` into its raw parts.
     ///
-    /// match arena.try_insert(42) {
-    ///     Ok(idx) => {
+    /// See `Index::into_raw_parts` for details.
+    pub fn into_raw_parts(self) -> (u32, u32) {
+        (self.index, self.generation())
+    }
+}
+
+impl fmt::Debug for CompactIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompactIndex")
+            .field("index", &self.index)
+            .field("generation", &self.generation())
+            .finish()
+    }
+}
+
+impl From<CompactIndex> for Index {
+    fn from(index: CompactIndex) -> Index {
+        index.into_index()
+    }
+}
+
+/// Decides which free slot an `Arena` reuses for its next insertion.
+///
+/// This trait is sealed: it is implemented by `Lifo`, `Fifo` and
+/// `LowestFirst` only. The policy is the third type parameter of `Arena`, so
+/// it is chosen when the arena is constructed and kept by `Clone` and serde.
+///
+/// # Examples
+///
+/// ```
+/// use generational_arena::{Arena, Fifo, Index};
+///
+/// let mut arena = Arena::<_, Index, Fifo>::with_capacity_and_key(2);
+/// let a = arena.insert("a");
+/// let b = arena.insert("b");
+/// arena.remove(a);
+/// arena.remove(b);
+///
+/// // The oldest freed slot is reused first.
+/// let c = arena.insert("c");
+/// assert_eq!(c.into_raw_parts().0, a.into_raw_parts().0);
+/// ```
+pub trait ReusePolicy: sealed::Sealed {}
+
+mod sealed {
+    use super::{cmp, fmt, mem, BinaryHeap};
+
+    pub trait Sealed {
+        const KIND: ReuseKind;
+        // The state of the free list besides its head, which only some
+        // policies need.
+        type FreeSlots: FreeSlots;
+    }
+
+    // Policies without a tail or a heap ignore writes to them, and read them
+    // as empty.
+    pub trait FreeSlots: Clone + fmt::Debug + Default {
+        fn tail(&self) -> Option<usize> {
+            None
+        }
+
+        fn set_tail(&mut self, tail: Option<usize>) {
+            let _ = tail;
+        }
+
+        fn heap(&self) -> Option<&BinaryHeap<cmp::Reverse<usize>>> {
+            None
+        }
+
+        fn heap_mut(&mut self) -> Option<&mut BinaryHeap<cmp::Reverse<usize>>> {
+            None
+        }
+
+        // Keeps only the slots of the heap for which `f` returns true.
+        fn retain_heap(&mut self, f: impl Fn(usize) -> bool) {
+            if let Some(heap) = self.heap_mut() {
+                let slots = mem::take(heap).into_vec();
+                *heap = slots.into_iter().filter(|&cmp::Reverse(i)| f(i)).collect();
+            }
+        }
+    }
+
+    impl FreeSlots for () {}
+
+    #[derive(Clone, Debug, Default)]
+    pub struct FifoFreeSlots {
+        tail: Option<usize>,
+    }
+
+    impl FreeSlots for FifoFreeSlots {
+        fn tail(&self) -> Option<usize> {
+            self.tail
+        }
+
+        fn set_tail(&mut self, tail: Option<usize>) {
+            self.tail = tail;
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct LowestFirstFreeSlots {
+        heap: BinaryHeap<cmp::Reverse<usize>>,
+    }
+
+    impl FreeSlots for LowestFirstFreeSlots {
+        fn heap(&self) -> Option<&BinaryHeap<cmp::Reverse<usize>>> {
+            Some(&self.heap)
+        }
+
+        fn heap_mut(&mut self) -> Option<&mut BinaryHeap<cmp::Reverse<usize>>> {
+            Some(&mut self.heap)
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ReuseKind {
+        Lifo,
+        Fifo,
+        LowestFirst,
+    }
+}
+
+use sealed::{FreeSlots, ReuseKind};
+
+/// The default `ReusePolicy`: the most recently freed slot is reused first.
+///
+/// This is the cheapest policy, and keeps recently used memory warm.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lifo;
+
+/// A `ReusePolicy` that reuses the least recently freed slot first.
+///
+/// This maximizes the number of insertions before a freed slot, and hence its
+/// generation, is reused.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fifo;
+
+/// A `ReusePolicy` that reuses the free slot with the lowest index first.
+///
+/// This keeps the elements packed at the front of the arena, at the cost of
+/// keeping the free slots in a binary heap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LowestFirst;
+
+impl sealed::Sealed for Lifo {
+    const KIND: ReuseKind = ReuseKind::Lifo;
+    type FreeSlots = ();
+}
+
+impl sealed::Sealed for Fifo {
+    const KIND: ReuseKind = ReuseKind::Fifo;
+    type FreeSlots = sealed::FifoFreeSlots;
+}
+
+impl sealed::Sealed for LowestFirst {
+    const KIND: ReuseKind = ReuseKind::LowestFirst;
+    type FreeSlots = sealed::LowestFirstFreeSlots;
+}
+
+impl ReusePolicy for Lifo {}
+impl ReusePolicy for Fifo {}
+impl ReusePolicy for LowestFirst {}
+
+const DEFAULT_CAPACITY: usize = 4;
+
+impl<T, K, P> Default for Arena<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    fn default() -> Arena<T, K, P> {
+        Arena::with_key()
+    }
+}
+
+impl<T> Arena<T> {
+    /// Constructs a new, empty `Arena`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::<usize>::new();
+    /// # let _ = arena;
+    /// ```
+    pub fn new() -> Arena<T> {
+        Arena::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Constructs a new, empty `Arena<T>` with the specified capacity.
+    ///
+    /// The `Arena<T>` will be able to hold `n` elements without further allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::with_capacity(10);
+    ///
+    /// // These insertions will not require further allocation.
+    /// for i in 0..10 {
+    ///     assert!(arena.try_insert(i).is_ok());
+    /// }
+    ///
+    /// // But now we are at capacity, and there is no more room.
+    /// assert!(arena.try_insert(99).is_err());
+    /// ```
+    pub fn with_capacity(n: usize) -> Arena<T> {
+        Arena::with_capacity_and_key(n)
+    }
+}
+
+impl<T, K, P> Arena<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    /// Constructs a new, empty `Arena` that uses `K` as its index type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::TypedArena;
+    ///
+    /// let mut arena = TypedArena::<usize>::with_key();
+    /// # let _ = arena;
+    /// ```
+    pub fn with_key() -> Arena<T, K, P> {
+        Arena::with_capacity_and_key(DEFAULT_CAPACITY)
+    }
+
+    /// Constructs a new, empty `Arena` that uses `K` as its index type, with
+    /// the specified capacity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::TypedArena;
+    ///
+    /// let mut arena = TypedArena::with_capacity_and_key(10);
+    /// assert_eq!(arena.capacity(), 10);
+    /// # arena.insert(0);
+    /// ```
+    pub fn with_capacity_and_key(n: usize) -> Arena<T, K, P> {
+        Arena::with_capacity_and_generation(n, 0)
+    }
+
+    /// Constructs a new, empty `Arena` with the specified capacity, whose
+    /// slots start at the given generation rather than at zero.
+    ///
+    /// This is mostly useful to exercise the behavior of the arena when
+    /// generations run out, see `retired_count`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `generation` is larger than `K::MAX_GENERATION`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::<u32>::with_capacity_and_generation(1, 41);
+    /// let idx = arena.insert(123);
+    /// assert_eq!(idx.into_raw_parts(), (0, 41));
+    /// ```
+    pub fn with_capacity_and_generation(n: usize, generation: u64) -> Arena<T, K, P> {
+        assert!(
+            generation <= K::MAX_GENERATION,
+            "generation out of range for the arena's index type"
+        );
+        let n = cmp::max(n, 1);
+        let mut arena = Arena {
+            items: Vec::new(),
+            generation,
+            free_list_head: None,
+            free_slots: Default::default(),
+            quarantine: VecDeque::new(),
+            quarantine_len: 0,
+            reserved: AtomicUsize::new(0),
+            journal: None,
+            tracker: None,
+            changes: None,
+            len: 0,
+            retired: 0,
+            key: PhantomData,
+            policy: PhantomData,
+        };
+        arena.reserve(n);
+        arena
+    }
+
+    /// Clear all the items inside the arena, but keep its allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::with_capacity(1);
+    /// arena.insert(42);
+    /// arena.insert(43);
+    ///
+    /// arena.clear();
+    ///
+    /// assert_eq!(arena.capacity(), 2);
+    /// ```
+    pub fn clear(&mut self) {
+        self.flush_reserved();
+        self.touch_all();
+        self.record_all(Changes::remove);
+        let end = self.items.capacity();
+        let len = self.items.len();
+
+        // Only the generations of occupied slots are incremented, so that
+        // slots which were already free do not needlessly age.
+        for entry in self.items.iter_mut() {
+            *entry = match Self::next_generation(entry) {
+                Some(generation) => Entry::Free {
+                    next_free: None,
+                    generation,
+                },
+                None => Entry::Retired,
+            };
+        }
+        let generation = self.generation;
+        self.items.extend((len..end).map(|_| Entry::Free {
+            next_free: None,
+            generation,
+        }));
+        self.quarantine.clear();
+        self.rebuild_free_list();
+        self.len = 0;
+    }
+
+    /// Puts all the free slots that are not quarantined back into the free
+    /// list, so that they are reused in ascending order, and recounts the
+    /// retired slots.
+    fn rebuild_free_list(&mut self) {
+        self.rebuild_free_list_until(self.items.len());
+    }
+
+    /// Like `rebuild_free_list`, but only considers the slots before `end`.
+    fn rebuild_free_list_until(&mut self, end: usize) {
+        self.clear_free_list();
+        self.retired = 0;
+        let mut quarantined: Vec<usize> = self.quarantine.iter().copied().collect();
+        quarantined.sort_unstable();
+        for i in 0..end {
+            // `Lifo` reuses the last pushed slot first.
+            let i = match P::KIND {
+                ReuseKind::Lifo => end - 1 - i,
+                ReuseKind::Fifo | ReuseKind::LowestFirst => i,
+            };
+            match self.items[i] {
+                Entry::Free { .. } if quarantined.binary_search(&i).is_ok() => {}
+                Entry::Free { .. } => self.push_free(i),
+                Entry::Retired => self.retired += 1,
+                Entry::Occupied { .. } | Entry::Reserved { .. } => {}
+            }
+        }
+    }
+
+    fn clear_free_list(&mut self) {
+        self.free_list_head = None;
+        self.free_slots = Default::default();
+    }
+
+    /// Adds the free slot `i` to the free list.
+    fn push_free(&mut self, i: usize) {
+        match P::KIND {
+            ReuseKind::Lifo => {
+                let head = self.free_list_head;
+                self.set_next_free(i, head);
+                self.free_list_head = Some(i);
+            }
+            ReuseKind::Fifo => {
+                self.set_next_free(i, None);
+                match self.free_slots.tail() {
+                    Some(tail) => self.set_next_free(tail, Some(i)),
+                    None => self.free_list_head = Some(i),
+                }
+                self.free_slots.set_tail(Some(i));
+            }
+            ReuseKind::LowestFirst => {
+                if let Some(heap) = self.free_slots.heap_mut() {
+                    heap.push(cmp::Reverse(i));
+                }
+                self.log(Undo::HeapPush(i));
+            }
+        }
+    }
+
+    /// Takes the next slot to reuse off the free list.
+    #[inline]
+    fn pop_free(&mut self) -> Option<usize> {
+        match P::KIND {
+            ReuseKind::Lifo | ReuseKind::Fifo => {
+                let i = self.free_list_head?;
+                match self.items[i] {
+                    Entry::Free { next_free, .. } => self.free_list_head = next_free,
+                    _ => panic!("corrupt free list"),
+                }
+                if self.free_list_head.is_none() {
+                    self.free_slots.set_tail(None);
+                }
+                Some(i)
+            }
+            ReuseKind::LowestFirst => {
+                let cmp::Reverse(i) = self.free_slots.heap_mut()?.pop()?;
+                self.log(Undo::HeapPop(i));
+                Some(i)
+            }
+        }
+    }
+
+    /// Records that slot `i` is about to change: saves its state in the
+    /// journal of the ongoing transaction, if any, and marks it as changed
+    /// since the last snapshot.
+    #[inline]
+    fn touch(&mut self, i: usize) {
+        if let Some(journal) = &mut self.journal {
+            journal.touch(i, &self.items[i]);
+        }
+        if let Some(tracker) = &mut self.tracker {
+            tracker.mark(i);
+        }
+    }
+
+    /// Records a change to the free slot heap or the quarantine in the journal
+    /// of the ongoing transaction, if any.
+    #[inline]
+    fn log(&mut self, undo: Undo<T>) {
+        if let Some(journal) = &mut self.journal {
+            journal.log(undo);
+        }
+    }
+
+    /// Stops tracking the slots changed since the last snapshot, before a
+    /// change to many slots at once. Taking or restoring a snapshot copies
+    /// the whole arena afterwards.
+    fn touch_all(&mut self) {
+        self.tracker = None;
+    }
+
+    /// Records a change to the element at index `i`, if changes are tracked,
+    /// and saves how it was recorded before in the journal of the ongoing
+    /// transaction, if any.
+    #[inline]
+    fn record(&mut self, i: Index, f: fn(&mut Changes, Index)) {
+        if let Some(changes) = &mut self.changes {
+            if let Some(journal) = &mut self.journal {
+                journal.log(Undo::Change(i, changes.get(i)));
+            }
+            f(changes, i);
+        }
+    }
+
+    /// Records a change to every element, if changes are tracked.
+    fn record_all(&mut self, f: fn(&mut Changes, Index)) {
+        if self.changes.is_none() {
+            return;
+        }
+        for i in 0..self.items.len() {
+            if let Entry::Occupied { generation, .. } = self.items[i] {
+                self.record(Index::new(i, generation), f);
+            }
+        }
+    }
+
+    /// Takes the free slot `i` off the free list, or out of quarantine.
+    fn unlink_free(&mut self, i: usize) {
+        if let Some(position) = self.quarantine.iter().position(|&j| j == i) {
+            self.quarantine.remove(position);
+            return;
+        }
+        match P::KIND {
+            ReuseKind::Lifo | ReuseKind::Fifo => {
+                let next = match self.items[i] {
+                    Entry::Free { next_free, .. } => next_free,
+                    _ => panic!("corrupt free list"),
+                };
+                if self.free_list_head == Some(i) {
+                    self.free_list_head = next;
+                    if next.is_none() {
+                        self.free_slots.set_tail(None);
+                    }
+                    return;
+                }
+                let mut prev = self.free_list_head.expect("corrupt free list");
+                loop {
+                    match self.items[prev] {
+                        Entry::Free {
+                            next_free: Some(j), ..
+                        } if j == i => break,
+                        Entry::Free {
+                            next_free: Some(j), ..
+                        } => prev = j,
+                        _ => panic!("corrupt free list"),
+                    }
+                }
+                self.set_next_free(prev, next);
+                if self.free_slots.tail() == Some(i) {
+                    self.free_slots.set_tail(Some(prev));
+                }
+            }
+            ReuseKind::LowestFirst => self.free_slots.retain_heap(|j| j != i),
+        }
+    }
+
+    fn set_next_free(&mut self, i: usize, next: Option<usize>) {
+        self.touch(i);
+        match &mut self.items[i] {
+            Entry::Free { next_free, .. } => *next_free = next,
+            _ => panic!("corrupt free list"),
+        }
+    }
+
+    /// Drops the free slots at the end of the arena, keeping at least
+    /// `min_capacity` slots, and unlinks them from the free list.
+    fn truncate_free_slots(&mut self, min_capacity: usize) {
+        self.flush_reserved();
+        let mut new_len = self.items.len();
+        while new_len > min_capacity {
+            match self.items[new_len - 1] {
+                Entry::Free { generation, .. } => {
+                    // Slots that are created again at this position later
+                    // must not resurrect stale indices to the dropped one.
+                    self.generation = cmp::max(self.generation, generation);
+                    new_len -= 1;
+                }
+                _ => break,
+            }
+        }
+        if new_len == self.items.len() {
+            return;
+        }
+        self.unlink_free_slots_from(new_len);
+        self.items.truncate(new_len);
+        if let Some(tracker) = &mut self.tracker {
+            tracker.truncate(new_len);
+        }
+    }
+
+    /// Takes the free slots from `end` onwards off the free list and out of
+    /// quarantine, keeping the others in the order they are reused in.
+    fn unlink_free_slots_from(&mut self, end: usize) {
+        match P::KIND {
+            ReuseKind::Lifo | ReuseKind::Fifo => {
+                let mut last = None;
+                let mut next = self.free_list_head;
+                while let Some(i) = next {
+                    next = self.next_free(i);
+                    if i >= end {
+                        continue;
+                    }
+                    match last {
+                        Some(last) if self.next_free(last) != Some(i) => {
+                            self.set_next_free(last, Some(i))
+                        }
+                        Some(_) => {}
+                        None => self.free_list_head = Some(i),
+                    }
+                    last = Some(i);
+                }
+                match last {
+                    Some(last) if self.next_free(last).is_some() => self.set_next_free(last, None),
+                    Some(_) => {}
+                    None => self.free_list_head = None,
+                }
+                self.free_slots.set_tail(last);
+            }
+            ReuseKind::LowestFirst => self.free_slots.retain_heap(|i| i < end),
+        }
+        self.quarantine.retain(|&i| i < end);
+    }
+
+    fn next_free(&self, i: usize) -> Option<usize> {
+        match self.items[i] {
+            Entry::Free { next_free, .. } => next_free,
+            _ => panic!("corrupt free list"),
+        }
+    }
+
+    /// Frees the slot `i`, which must be `Entry::Free`, by putting it in
+    /// quarantine, or directly on the free list if there is no quarantine.
+    fn release(&mut self, i: usize) {
+        if self.quarantine_len == 0 {
+            self.push_free(i);
+            return;
+        }
+        self.quarantine.push_back(i);
+        self.log(Undo::QuarantinePush);
+        if self.quarantine.len() > self.quarantine_len {
+            let oldest = self.quarantine.pop_front().unwrap();
+            self.log(Undo::QuarantinePop(oldest));
+            self.push_free(oldest);
+        }
+    }
+
+    /// Attempts to insert `value` into the arena using existing capacity.
+    ///
+    /// This method will never allocate new capacity in the arena.
+    ///
+    /// If insertion succeeds, then the `value`'s index is returned. If
+    /// insertion fails, then `Err(value)` is returned to give ownership of
+    /// `value` back to the caller.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// match arena.try_insert(42) {
+    ///     Ok(idx) => {
     ///         // Insertion succeeded.
     ///         assert_eq!(arena[idx], 42);
     ///     }
@@ -387,128 +1424,1058 @@ impl<T> Arena<T> {
     ///     }
     /// };
     /// ```
-    #[inline]
-    pub fn try_insert(&mut self, value: T) -> Result<Index, T> {
-        match self.try_alloc_next_index() {
-            None => Err(value),
-            Some(index) => {
-                self.items[index.index] = Entry::Occupied {
-                    generation: self.generation,
+    #[inline]
+    pub fn try_insert(&mut self, value: T) -> Result<K, T> {
+        match self.try_alloc_next_index() {
+            None => Err(value),
+            Some(index) => {
+                self.len += 1;
+                self.items[index.index] = Entry::Occupied {
+                    generation: index.generation(),
+                    value,
+                };
+                self.record(index, Changes::add);
+                Ok(K::from_index(index))
+            },
+        }
+    }
+
+    /// Attempts to insert the value returned by `create` into the arena using existing capacity.
+    /// `create` is called with the new value's associated index, allowing values that know their own index.
+    ///
+    /// This method will never allocate new capacity in the arena.
+    ///
+    /// If insertion succeeds, then the new index is returned. If
+    /// insertion fails, then `Err(create)` is returned to give ownership of
+    /// `create` back to the caller.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, Index};
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// match arena.try_insert_with(|idx| (42, idx)) {
+    ///     Ok(idx) => {
+    ///         // Insertion succeeded.
+    ///         assert_eq!(arena[idx].0, 42);
+    ///         assert_eq!(arena[idx].1, idx);
+    ///     }
+    ///     Err(x) => {
+    ///         // Insertion failed.
+    ///     }
+    /// };
+    /// ```
+    #[inline]
+    pub fn try_insert_with<F: FnOnce(K) -> T>(&mut self, create: F) -> Result<K, F> {
+        match self.try_alloc_next_index() {
+            None => Err(create),
+            Some(index) => {
+                self.len += 1;
+                self.items[index.index] = Entry::Occupied {
+                    generation: index.generation(),
+                    value: create(K::from_index(index)),
+                };
+                self.record(index, Changes::add);
+                Ok(K::from_index(index))
+            },
+        }
+    }
+
+    #[inline]
+    fn try_alloc_next_index(&mut self) -> Option<Index> {
+        let i = self.pop_free()?;
+        self.touch(i);
+        match self.items[i] {
+            Entry::Free { generation, .. } => Some(Index::new(i, generation)),
+            _ => panic!("corrupt free list"),
+        }
+    }
+
+    /// Insert `value` into the arena, allocating more capacity if necessary.
+    ///
+    /// The `value`'s associated index in the arena is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// let idx = arena.insert(42);
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    #[inline]
+    pub fn insert(&mut self, value: T) -> K {
+        match self.try_insert(value) {
+            Ok(i) => i,
+            Err(value) => self.insert_slow_path(value),
+        }
+    }
+
+    /// Insert the value returned by `create` into the arena, allocating more capacity if necessary.
+    /// `create` is called with the new value's associated index, allowing values that know their own index.
+    ///
+    /// The new value's associated index in the arena is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, Index};
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// let idx = arena.insert_with(|idx| (42, idx));
+    /// assert_eq!(arena[idx].0, 42);
+    /// assert_eq!(arena[idx].1, idx);
+    /// ```
+    #[inline]
+    pub fn insert_with(&mut self, create: impl FnOnce(K) -> T) -> K {
+        match self.try_insert_with(create) {
+            Ok(i) => i,
+            Err(create) => self.insert_with_slow_path(create),
+        }
+    }
+
+    /// Like `insert_with`, but also returns the inserted element.
+    pub(crate) fn insert_with_mut(&mut self, create: impl FnOnce(K) -> T) -> (K, &mut T) {
+        let i = self.insert_with(create);
+        match &mut self.items[i.into_index().index] {
+            Entry::Occupied { value, .. } => (i, value),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reserve an index for an element that will be filled in later with
+    /// `fill`, allocating more capacity if necessary.
+    ///
+    /// Until it is filled, the reserved slot holds no element: `get`, `iter`
+    /// and `len` ignore it, and `retain` leaves it alone. A reservation can be
+    /// given up with `cancel`; `clear` and `drain` cancel all reservations.
+    /// Reserved slots are serialized as vacant slots, so reservations do not
+    /// survive serialization.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// struct Node {
+    ///     next: generational_arena::Index,
+    /// }
+    ///
+    /// // Build a two-node cycle.
+    /// let mut arena = Arena::new();
+    /// let a = arena.reserve_index();
+    /// let b = arena.insert(Node { next: a });
+    /// assert!(arena.get(a).is_none());
+    /// assert_eq!(arena.len(), 1);
+    ///
+    /// assert!(arena.fill(a, Node { next: b }).is_ok());
+    /// assert_eq!(arena[arena[a].next].next, a);
+    /// ```
+    pub fn reserve_index(&mut self) -> K {
+        let index = match self.try_alloc_next_index() {
+            Some(index) => index,
+            None => {
+                self.grow();
+                self.try_alloc_next_index()
+                    .expect("an index is always available after growing")
+            }
+        };
+        self.items[index.index] = Entry::Reserved {
+            generation: index.generation(),
+        };
+        K::from_index(index)
+    }
+
+    /// Fill the slot reserved for index `i` with `value`.
+    ///
+    /// If `i` has been reserved with `reserve_index`, and not filled or
+    /// cancelled yet, then `value` is inserted at `i`. Otherwise,
+    /// `Err(value)` is returned to give ownership of `value` back to the
+    /// caller.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.reserve_index();
+    ///
+    /// assert_eq!(arena.fill(idx, 42), Ok(()));
+    /// assert_eq!(arena[idx], 42);
+    /// assert_eq!(arena.fill(idx, 43), Err(43));
+    /// ```
+    pub fn fill(&mut self, i: K, value: T) -> Result<(), T> {
+        self.flush_reserved();
+        let i = i.into_index();
+        match self.items.get(i.index) {
+            Some(Entry::Reserved { generation }) if *generation == i.generation() => {
+                self.touch(i.index);
+                self.items[i.index] = Entry::Occupied {
+                    generation: i.generation(),
                     value,
                 };
-                Ok(index)
-            },
+                self.len += 1;
+                self.record(i, Changes::add);
+                Ok(())
+            }
+            _ => Err(value),
+        }
+    }
+
+    /// Give up the reservation of index `i`.
+    ///
+    /// The reserved slot is freed like the slot of a removed element, so `i`
+    /// becomes stale. Returns `true` if `i` was reserved and not filled or
+    /// cancelled yet, and `false` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.reserve_index();
+    ///
+    /// assert!(arena.cancel(idx));
+    /// assert!(!arena.cancel(idx));
+    /// assert_eq!(arena.fill(idx, 42), Err(42));
+    /// ```
+    pub fn cancel(&mut self, i: K) -> bool {
+        self.flush_reserved();
+        let i = i.into_index();
+        match self.items.get(i.index) {
+            Some(Entry::Reserved { generation }) if *generation == i.generation() => {}
+            _ => return false,
+        }
+        self.touch(i.index);
+        match Self::bump_generation(i.generation()) {
+            Some(generation) => {
+                self.items[i.index] = Entry::Free {
+                    next_free: None,
+                    generation,
+                };
+                self.release(i.index);
+            }
+            None => {
+                self.items[i.index] = Entry::Retired;
+                self.retired += 1;
+            }
+        }
+        true
+    }
+
+    /// Reserve an index through a shared reference, so that several threads
+    /// can reserve indices at the same time.
+    ///
+    /// The index refers to a new slot past the end of the arena; free slots
+    /// are never reused by this method. The slot is only added to the arena
+    /// by `flush_reserved`, which every method that takes `&mut self` and
+    /// needs it calls first, such as `fill`, `cancel`, `reserve` or `clear`.
+    /// Until then, the arena's capacity does not include it. Afterwards, the
+    /// reservation behaves like one made by `reserve_index`: the element is
+    /// filled in with `fill`, or the reservation is given up with `cancel`.
+    /// Clones of the arena do not get the reservations that have not been
+    /// added yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new slot could not be addressed by the arena's index
+    /// type (see `ArenaKey::MAX_INDEX`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use std::thread;
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// let indices: Vec<_> = thread::scope(|s| {
+    ///     let jobs: Vec<_> = (0..4)
+    ///         .map(|_| s.spawn(|| arena.reserve_index_shared()))
+    ///         .collect();
+    ///     jobs.into_iter().map(|job| job.join().unwrap()).collect()
+    /// });
+    ///
+    /// arena.flush_reserved();
+    /// for (i, idx) in indices.iter().enumerate() {
+    ///     assert_eq!(arena.fill(*idx, i), Ok(()));
+    /// }
+    /// assert_eq!(arena.len(), 4);
+    /// ```
+    pub fn reserve_index_shared(&self) -> K {
+        self.reserver().reserve_index()
+    }
+
+    /// Get a handle that reserves indices like `reserve_index_shared`.
+    ///
+    /// The reserved slots stay reserved until they are filled or cancelled;
+    /// see `Commands::cancel` for giving up the reservations of a command
+    /// buffer that is not applied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let reserver = arena.reserver();
+    /// let a = reserver.reserve_index();
+    /// let b = reserver.reserve_index();
+    /// assert_ne!(a, b);
+    ///
+    /// assert_eq!(arena.fill(b, "b"), Ok(()));
+    /// assert_eq!(arena.fill(a, "a"), Ok(()));
+    /// ```
+    pub fn reserver(&self) -> Reserver<'_, K> {
+        Reserver::new(&self.reserved, self.items.len(), self.generation)
+    }
+
+    /// Add the slots reserved by `reserve_index_shared` to the arena.
+    ///
+    /// The reserved indices can be filled or cancelled afterwards. This is
+    /// called automatically by the methods that need it, so calling it
+    /// explicitly is only necessary to make the reserved slots count towards
+    /// `capacity`, for example at a synchronization point after parallel jobs
+    /// have reserved indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::with_capacity(1);
+    /// let idx = arena.reserve_index_shared();
+    /// assert_eq!(arena.capacity(), 1);
+    ///
+    /// arena.flush_reserved();
+    /// assert_eq!(arena.capacity(), 2);
+    /// assert!(arena.get(idx).is_none());
+    ///
+    /// assert_eq!(arena.fill(idx, "spawned"), Ok(()));
+    /// assert_eq!(arena[idx], "spawned");
+    /// ```
+    pub fn flush_reserved(&mut self) {
+        let reserved = mem::replace(self.reserved.get_mut(), 0);
+        if reserved == 0 {
+            return;
+        }
+        let generation = self.generation;
+        self.items
+            .extend((0..reserved).map(|_| Entry::Reserved { generation }));
+    }
+
+    /// Apply the insertions, removals and replacements recorded in `commands`,
+    /// in order.
+    ///
+    /// Returns the indices of the commands that could not be applied because
+    /// their index was stale: removals and replacements of elements that are
+    /// not in the arena anymore, and insertions whose reservation has been
+    /// given up, for example by `clear`. The values of those commands are
+    /// dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    /// use generational_arena::commands::Commands;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    ///
+    /// let mut commands = Commands::new();
+    /// let new_idx = commands.insert(&arena.reserver(), 2);
+    /// commands.remove(idx);
+    /// commands.remove(idx);
+    ///
+    /// assert_eq!(arena.apply(commands), vec![idx]);
+    /// assert_eq!(arena[new_idx], 2);
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    pub fn apply(&mut self, commands: Commands<T, K>) -> Vec<K> {
+        let mut stale = Vec::new();
+        for command in commands.into_commands() {
+            match command {
+                Command::Insert(i, value) => {
+                    if self.fill(i, value).is_err() {
+                        stale.push(i);
+                    }
+                }
+                Command::Remove(i) => {
+                    if self.remove(i).is_none() {
+                        stale.push(i);
+                    }
+                }
+                Command::Replace(i, value) => match self.get_mut(i) {
+                    Some(element) => *element = value,
+                    None => stale.push(i),
+                },
+            }
+        }
+        stale
+    }
+
+    /// Begin a transaction: a batch of insertions, removals and changes to
+    /// elements that is either committed or rolled back as a whole.
+    ///
+    /// Rolling back restores the elements, the free slots and the generations
+    /// of the arena, in time proportional to the number of changes. To allow
+    /// this, the elements that the transaction removes or borrows mutably are
+    /// cloned. If changes are tracked (see `set_track_changes`), the changes
+    /// that the transaction records are rolled back as well. Dropping the
+    /// transaction without committing it rolls it back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    ///
+    /// let mut tx = arena.begin();
+    /// *tx.get_mut(idx).unwrap() = 2;
+    /// let new_idx = tx.insert(3);
+    /// tx.rollback();
+    ///
+    /// assert_eq!(arena[idx], 1);
+    /// assert!(!arena.contains(new_idx));
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    pub fn begin(&mut self) -> Transaction<'_, T, K, P>
+    where
+        T: Clone,
+    {
+        Transaction::new(self)
+    }
+
+    /// Run `f` in a transaction, see `begin`. The transaction is committed if
+    /// `f` returns `Ok`, and rolled back if it returns `Err`, or panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(10);
+    ///
+    /// let result: Result<(), &str> = arena.transaction(|tx| {
+    ///     *tx.get_mut(idx).unwrap() -= 20;
+    ///     if tx.get(idx) < Some(&0) {
+    ///         return Err("overdrawn");
+    ///     }
+    ///     Ok(())
+    /// });
+    ///
+    /// assert_eq!(result, Err("overdrawn"));
+    /// assert_eq!(arena[idx], 10);
+    /// ```
+    pub fn transaction<R, E>(
+        &mut self,
+        f: impl FnOnce(&mut Transaction<'_, T, K, P>) -> Result<R, E>,
+    ) -> Result<R, E>
+    where
+        T: Clone,
+    {
+        let mut tx = self.begin();
+        let result = f(&mut tx);
+        if result.is_ok() {
+            tx.commit();
+        }
+        result
+    }
+
+    /// Take a snapshot of the arena, which it can be restored to later with
+    /// `restore`.
+    ///
+    /// Snapshots share the slots that did not change between them. The first
+    /// snapshot clones every slot of the arena; afterwards, the arena keeps
+    /// track of the slots that change, and the next snapshot only clones the
+    /// chunks of a few slots that hold those, along with the free slot heap
+    /// of `LowestFirst` arenas and the quarantine.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert("original");
+    ///
+    /// let snapshot = arena.snapshot();
+    /// arena[idx] = "edited";
+    /// arena.insert("new");
+    ///
+    /// arena.restore(&snapshot);
+    /// assert_eq!(arena[idx], "original");
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    pub fn snapshot(&mut self) -> ArenaSnapshot<T, K, P>
+    where
+        T: Clone,
+    {
+        self.flush_reserved();
+        let tracker = self.tracker.get_or_insert_with(|| Box::new(Tracker::new()));
+        ArenaSnapshot {
+            items: tracker.sync(&self.items),
+            generation: self.generation,
+            free_list_head: self.free_list_head,
+            free_slots: self.free_slots.clone(),
+            quarantine: self.quarantine.clone(),
+            quarantine_len: self.quarantine_len,
+            len: self.len,
+            retired: self.retired,
+            key: PhantomData,
+        }
+    }
+
+    /// Restore the arena to the state it was in when `snapshot` was taken:
+    /// the same elements at the same indices, and the same free slots to be
+    /// reused in the same order. The indices handed out since then may be
+    /// handed out again.
+    ///
+    /// Only the slots that changed since the last snapshot taken or restored
+    /// are copied, along with those in which that snapshot and `snapshot`
+    /// differ, in chunks of a few slots. This holds as long as the arena was
+    /// only changed one slot at a time: by inserting, removing or getting
+    /// elements mutably. Methods that change many slots at once, such as
+    /// `iter_mut`, `retain`, `clear` or `compact`, stop the tracking of
+    /// changes, and restoring copies the whole snapshot then.
+    ///
+    /// If changes are tracked (see `set_track_changes`), restoring records the
+    /// elements that it adds, removes or may change, like any other change.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let mut undo = Vec::new();
+    ///
+    /// let a = arena.insert(1);
+    /// undo.push(arena.snapshot());
+    /// let b = arena.insert(2);
+    /// undo.push(arena.snapshot());
+    /// arena.remove(a);
+    ///
+    /// arena.restore(&undo.pop().unwrap());
+    /// assert_eq!((arena[a], arena[b]), (1, 2));
+    /// arena.restore(&undo.pop().unwrap());
+    /// assert_eq!(arena[a], 1);
+    /// assert!(!arena.contains(b));
+    /// ```
+    pub fn restore(&mut self, snapshot: &ArenaSnapshot<T, K, P>)
+    where
+        T: Clone,
+    {
+        self.flush_reserved();
+        let target = &snapshot.items;
+        let mut tracker = self
+            .tracker
+            .take()
+            .unwrap_or_else(|| Box::new(Tracker::new()));
+        let (slots, start) = tracker.changed_slots(self.items.len(), target);
+        for i in slots {
+            let entry = target.get(i).expect("changed slots are in the snapshot");
+            self.record_slot(i, Some(entry));
+            self.items[i].clone_from(entry);
+        }
+        for i in start..cmp::max(self.items.len(), target.len()) {
+            self.record_slot(i, target.get(i));
+        }
+        self.items.truncate(start);
+        self.items.extend(target.iter().skip(start).cloned());
+        self.tracker = Some(tracker);
+
+        self.generation = snapshot.generation;
+        self.free_list_head = snapshot.free_list_head;
+        self.free_slots.clone_from(&snapshot.free_slots);
+        self.quarantine.clone_from(&snapshot.quarantine);
+        self.quarantine_len = snapshot.quarantine_len;
+        self.len = snapshot.len;
+        self.retired = snapshot.retired;
+    }
+
+    /// Records the change to the element in slot `i` when the slot is replaced
+    /// with `entry`, or dropped if `entry` is `None`.
+    fn record_slot(&mut self, i: usize, entry: Option<&Entry<T>>) {
+        let changes = match &mut self.changes {
+            Some(changes) => changes,
+            None => return,
+        };
+        let old = match self.items.get(i) {
+            Some(Entry::Occupied { generation, .. }) => Some(Index::new(i, *generation)),
+            _ => None,
+        };
+        let new = match entry {
+            Some(Entry::Occupied { generation, .. }) => Some(Index::new(i, *generation)),
+            _ => None,
+        };
+        match (old, new) {
+            (Some(old), Some(new)) if old == new => changes.modify(old),
+            (old, new) => {
+                if let Some(old) = old {
+                    changes.remove(old);
+                }
+                if let Some(new) = new {
+                    changes.add(new);
+                }
+            }
+        }
+    }
+
+    /// Start or stop tracking the elements added to, modified in and removed
+    /// from the arena since the last checkpoint.
+    ///
+    /// While changes are tracked, inserting an element records its index as
+    /// added, borrowing it mutably records it as modified, and removing it
+    /// records it as removed. Methods that borrow every element mutably, such
+    /// as `iter_mut` and `retain`, record every element that they leave in the
+    /// arena as modified. Starting to track changes starts with no change
+    /// recorded, and stopping discards the recorded changes.
+    ///
+    /// Changes are not tracked by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let a = arena.insert("a");
+    /// assert!(arena.changes().is_none());
+    ///
+    /// arena.set_track_changes(true);
+    /// let b = arena.insert("b");
+    /// arena[a] = "A";
+    ///
+    /// let changes = arena.changes().unwrap();
+    /// assert!(changes.added().contains(&b));
+    /// assert!(changes.modified().contains(&a));
+    /// ```
+    pub fn set_track_changes(&mut self, track: bool) {
+        match (track, &self.changes) {
+            (true, None) => self.changes = Some(Box::default()),
+            (false, Some(_)) => self.changes = None,
+            _ => {}
+        }
+    }
+
+    /// Get the changes made to the arena since the last checkpoint, or `None`
+    /// if changes are not tracked. See `set_track_changes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.set_track_changes(true);
+    /// let idx = arena.insert(42);
+    ///
+    /// for &added in arena.changes().unwrap().added() {
+    ///     assert_eq!(arena[added], 42);
+    /// }
+    /// ```
+    pub fn changes(&self) -> Option<&Changes> {
+        self.changes.as_deref()
+    }
+
+    /// Return the changes made to the arena since the last checkpoint, and
+    /// start recording changes anew from here. Returns `None` if changes are
+    /// not tracked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.set_track_changes(true);
+    /// let idx = arena.insert(42);
+    ///
+    /// let changes = arena.checkpoint().unwrap();
+    /// assert!(changes.added().contains(&idx));
+    /// assert!(arena.changes().unwrap().is_empty());
+    /// ```
+    pub fn checkpoint(&mut self) -> Option<Changes> {
+        self.changes.as_deref_mut().map(mem::take)
+    }
+
+    /// Describe the differences between `old` and `new`, so that applying the
+    /// resulting delta to a copy of `old` turns it into a copy of `new`. See
+    /// `apply_delta`.
+    ///
+    /// Only the slots that differ are included, along with the free slots
+    /// waiting to be reused and the other bookkeeping of `new`. Elements are
+    /// compared with `PartialEq`, and only the changed ones are cloned. Pending
+    /// shared reservations of `new` are not slots yet, and are left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut server = Arena::new();
+    /// server.insert("a");
+    /// let mut client = server.clone();
+    ///
+    /// let old = server.clone();
+    /// let b = server.insert("b");
+    /// let delta = Arena::diff(&old, &server);
+    ///
+    /// client.apply_delta(delta).unwrap();
+    /// assert_eq!(client[b], "b");
+    /// assert_eq!(client.insert("c"), server.insert("c"));
+    /// ```
+    pub fn diff(old: &Self, new: &Self) -> ArenaDelta<T, K, P>
+    where
+        T: Clone + PartialEq,
+    {
+        let slots = new
+            .items
+            .iter()
+            .enumerate()
+            .filter(|&(i, entry)| old.items.get(i) != Some(entry))
+            .map(|(i, entry)| (i, entry.clone()))
+            .collect();
+        ArenaDelta {
+            base_capacity: old.items.len(),
+            base_len: old.len,
+            capacity: new.items.len(),
+            slots,
+            generation: new.generation,
+            free_list_head: new.free_list_head,
+            free_slots: new.free_slots.clone(),
+            quarantine: new.quarantine.iter().cloned().collect(),
+            quarantine_len: new.quarantine_len,
+            len: new.len,
+            retired: new.retired,
+            key: PhantomData,
+        }
+    }
+
+    /// Apply a delta created by `diff`, turning this arena, which must be
+    /// equal to the delta's `old` arena, into a copy of its `new` arena: the
+    /// same elements at the same indices, and the same free slots to be reused
+    /// in the same order.
+    ///
+    /// If this arena's capacity or length differ from those of `old`, or if
+    /// the delta, for example a deserialized one, does not describe a
+    /// consistent arena once applied to this one, an error is returned and the
+    /// arena is left untouched. Other differences are not detected: applying a
+    /// delta to an arena with the same capacity and length as `old` but other
+    /// elements leaves it in an unspecified state, in which later operations
+    /// may panic.
+    ///
+    /// Like `clone_from`, this gives up the arena's pending shared
+    /// reservations, whose indices would not match the new slots.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, DeltaError};
+    ///
+    /// let mut arena = Arena::new();
+    /// let a = arena.insert(1);
+    /// let b = arena.insert(2);
+    /// let mut replica = arena.clone();
+    ///
+    /// let old = arena.clone();
+    /// arena.remove(a);
+    /// arena[b] = 3;
+    /// let delta = Arena::diff(&old, &arena);
+    /// assert_eq!(replica.apply_delta(delta.clone()), Ok(()));
+    ///
+    /// assert!(!replica.contains(a));
+    /// assert_eq!(replica[b], 3);
+    ///
+    /// // The replica is not equal to `old` anymore.
+    /// assert_eq!(
+    ///     replica.apply_delta(delta),
+    ///     Err(DeltaError::LenMismatch { expected: 2, found: 1 }),
+    /// );
+    /// ```
+    pub fn apply_delta(&mut self, delta: ArenaDelta<T, K, P>) -> Result<(), DeltaError> {
+        if self.items.len() != delta.base_capacity {
+            return Err(DeltaError::CapacityMismatch {
+                expected: delta.base_capacity,
+                found: self.items.len(),
+            });
+        }
+        if self.len != delta.base_len {
+            return Err(DeltaError::LenMismatch {
+                expected: delta.base_len,
+                found: self.len,
+            });
+        }
+        if !self.fits_delta(&delta) {
+            return Err(DeltaError::Invalid);
+        }
+        self.touch_all();
+        for i in delta.capacity..self.items.len() {
+            self.record_slot(i, None);
+        }
+        self.items.truncate(delta.capacity);
+        for (i, entry) in delta.slots {
+            self.record_slot(i, Some(&entry));
+            if i < self.items.len() {
+                self.items[i] = entry;
+            } else {
+                // `fits_delta` checked that the new slots come in order.
+                self.items.push(entry);
+            }
+        }
+        self.generation = delta.generation;
+        self.free_list_head = delta.free_list_head;
+        self.free_slots = delta.free_slots;
+        self.quarantine = delta.quarantine.into();
+        self.quarantine_len = delta.quarantine_len;
+        *self.reserved.get_mut() = 0;
+        self.len = delta.len;
+        self.retired = delta.retired;
+        Ok(())
+    }
+
+    /// Checks that applying `delta` to this arena, which matches its old
+    /// arena, leaves it consistent: the new slots all come in order, the
+    /// counts match the slots, and the free list, heap and quarantine only
+    /// hold free slots.
+    fn fits_delta(&self, delta: &ArenaDelta<T, K, P>) -> bool {
+        let capacity = delta.capacity;
+        let mut next_slot = 0;
+        let mut new_slots = 0;
+        for &(i, _) in &delta.slots {
+            if i < next_slot || i >= capacity {
+                return false;
+            }
+            next_slot = i + 1;
+            if i >= self.items.len() {
+                new_slots += 1;
+            }
+        }
+        if new_slots != capacity.saturating_sub(self.items.len()) {
+            return false;
+        }
+
+        // The slot `i` once the delta is applied.
+        let slot = |i: usize| {
+            if i >= capacity {
+                return None;
+            }
+            match delta.slots.binary_search_by_key(&i, |&(j, _)| j) {
+                Ok(k) => Some(&delta.slots[k].1),
+                Err(_) => self.items.get(i),
+            }
+        };
+        let is_free = |i: usize| matches!(slot(i), Some(Entry::Free { .. }));
+
+        // Count the elements and retired slots that the delta removes and
+        // adds.
+        let count = |entry: &Entry<T>| match entry {
+            Entry::Occupied { .. } => (1, 0),
+            Entry::Retired => (0, 1),
+            Entry::Free { .. } | Entry::Reserved { .. } => (0, 0),
+        };
+        let (mut removed_len, mut removed_retired) = (0, 0);
+        let replaced = delta.slots.iter().map(|&(i, _)| i);
+        let dropped = capacity..self.items.len();
+        for i in replaced.filter(|&i| i < self.items.len()).chain(dropped) {
+            let (len, retired) = count(&self.items[i]);
+            removed_len += len;
+            removed_retired += retired;
+        }
+        let (mut added_len, mut added_retired) = (0, 0);
+        for (_, entry) in &delta.slots {
+            let (len, retired) = count(entry);
+            added_len += len;
+            added_retired += retired;
+            if matches!(*entry, Entry::Free { next_free: Some(j), .. } if !is_free(j)) {
+                return false;
+            }
+        }
+        if self.len + added_len != delta.len + removed_len
+            || self.retired + added_retired != delta.retired + removed_retired
+        {
+            return false;
+        }
+
+        let tail = delta.free_slots.tail();
+        if P::KIND == ReuseKind::Fifo && delta.free_list_head.is_some() != tail.is_some() {
+            return false;
+        }
+        // The tail of the free list is its last slot.
+        if let Some(Entry::Free { next_free, .. }) = tail.and_then(slot) {
+            if next_free.is_some() {
+                return false;
+            }
         }
+        let heap = delta.free_slots.heap().into_iter().flatten();
+        let mut free_slots = delta
+            .free_list_head
+            .into_iter()
+            .chain(tail)
+            .chain(heap.map(|&cmp::Reverse(i)| i))
+            .chain(delta.quarantine.iter().copied());
+        delta.quarantine.len() <= delta.quarantine_len && free_slots.all(is_free)
     }
 
-    /// Attempts to insert the value returned by `create` into the arena using existing capacity.
-    /// `create` is called with the new value's associated index, allowing values that know their own index.
+    /// Insert `value` into the arena at index `i`, allocating more capacity if
+    /// necessary.
     ///
-    /// This method will never allocate new capacity in the arena.
+    /// This allows mirroring an arena, for example on the other side of a
+    /// network connection, so that both hand out identical indices. If the
+    /// slot of `i` holds an element already, that element is replaced and
+    /// returned. If it holds an element with a newer generation, or has been
+    /// freed at a newer generation than `i`'s, an error is returned instead,
+    /// because stale indices could otherwise become valid again.
     ///
-    /// If insertion succeeds, then the new index is returned. If
-    /// insertion fails, then `Err(create)` is returned to give ownership of
-    /// `create` back to the caller.
+    /// Taking a free slot off the free list takes time linear in the number
+    /// of free slots.
     ///
     /// # Examples
     ///
     /// ```
-    /// use generational_arena::{Arena, Index};
+    /// use generational_arena::{Arena, Index, InsertAtError};
     ///
-    /// let mut arena = Arena::new();
+    /// let mut server = Arena::new();
+    /// let mut client = Arena::new();
     ///
-    /// match arena.try_insert_with(|idx| (42, idx)) {
-    ///     Ok(idx) => {
-    ///         // Insertion succeeded.
-    ///         assert_eq!(arena[idx].0, 42);
-    ///         assert_eq!(arena[idx].1, idx);
-    ///     }
-    ///     Err(x) => {
-    ///         // Insertion failed.
-    ///     }
-    /// };
+    /// let a = server.insert("a");
+    /// server.remove(a);
+    /// let b = server.insert("b");
+    /// assert_eq!(client.insert_at(b, "b"), Ok(None));
+    /// assert_eq!(client.insert_at(b, "B"), Ok(Some("b")));
+    /// assert_eq!(client[b], "B");
+    ///
+    /// assert_eq!(
+    ///     client.insert_at(a, "a"),
+    ///     Err(InsertAtError::StaleGeneration { generation: 0, minimum: 1 }),
+    /// );
     /// ```
-    #[inline]
-    pub fn try_insert_with<F: FnOnce(Index) -> T>(&mut self, create: F) -> Result<Index, F> {
-        match self.try_alloc_next_index() {
-            None => Err(create),
-            Some(index) => {
-                self.items[index.index] = Entry::Occupied {
-                    generation: self.generation,
-                    value: create(index),
-                };
-                Ok(index)
-            },
+    pub fn insert_at(&mut self, i: K, value: T) -> Result<Option<T>, InsertAtError> {
+        self.flush_reserved();
+        let i = i.into_index();
+
+        // Free slots hold the oldest generation that they accept, and the
+        // slots that growing the arena creates start at `self.generation`.
+        let minimum = match self.items.get(i.index) {
+            Some(Entry::Free { generation, .. })
+            | Some(Entry::Occupied { generation, .. })
+            | Some(Entry::Reserved { generation }) => *generation,
+            Some(Entry::Retired) => return Err(InsertAtError::Retired),
+            None => self.generation,
+        };
+        if i.generation() < minimum {
+            return Err(InsertAtError::StaleGeneration {
+                generation: i.generation(),
+                minimum,
+            });
+        }
+        if i.index >= self.items.len() {
+            self.try_reserve(i.index - self.items.len() + 1)
+                .map_err(InsertAtError::Reserve)?;
         }
-    }
 
-    #[inline]
-    fn try_alloc_next_index(&mut self) -> Option<Index> {
-        match self.free_list_head {
-            None => None,
-            Some(i) => match self.items[i] {
-                Entry::Occupied { .. } => panic!("corrupt free list"),
-                Entry::Free { next_free } => {
-                    self.free_list_head = next_free;
-                    self.len += 1;
-                    Some(Index {
-                        index: i,
-                        generation: self.generation,
-                    })
+        match self.items[i.index] {
+            Entry::Free { .. } => {
+                self.unlink_free(i.index);
+                self.len += 1;
+            }
+            Entry::Reserved { .. } => self.len += 1,
+            _ => {}
+        }
+        self.touch(i.index);
+        let entry = mem::replace(
+            &mut self.items[i.index],
+            Entry::Occupied {
+                generation: i.generation(),
+                value,
+            },
+        );
+        match entry {
+            Entry::Occupied { generation, value } => {
+                if generation == i.generation() {
+                    self.record(i, Changes::modify);
+                } else {
+                    self.record(Index::new(i.index, generation), Changes::remove);
+                    self.record(i, Changes::add);
                 }
+                Ok(Some(value))
+            }
+            _ => {
+                self.record(i, Changes::add);
+                Ok(None)
             }
         }
     }
 
-    /// Insert `value` into the arena, allocating more capacity if necessary.
+    /// Insert `value` into the arena, allocating more capacity if necessary,
+    /// without panicking or aborting if that allocation fails.
     ///
-    /// The `value`'s associated index in the arena is returned.
+    /// If insertion succeeds, then the `value`'s index is returned. If growing
+    /// the arena fails, then `value` is returned along with the error, and the
+    /// arena is left unchanged, like by `try_reserve`.
     ///
     /// # Examples
     ///
     /// ```
     /// use generational_arena::Arena;
     ///
-    /// let mut arena = Arena::new();
+    /// let mut arena = Arena::with_capacity(1);
+    /// arena.insert(1);
     ///
-    /// let idx = arena.insert(42);
-    /// assert_eq!(arena[idx], 42);
+    /// match arena.try_insert_grow(2) {
+    ///     Ok(idx) => assert_eq!(arena[idx], 2),
+    ///     Err((value, e)) => eprintln!("could not insert {}: {}", value, e),
+    /// }
     /// ```
-    #[inline]
-    pub fn insert(&mut self, value: T) -> Index {
+    pub fn try_insert_grow(&mut self, value: T) -> Result<K, (T, TryReserveError)> {
         match self.try_insert(value) {
-            Ok(i) => i,
-            Err(value) => self.insert_slow_path(value),
+            Ok(i) => Ok(i),
+            Err(value) => {
+                if let Err(e) = self.try_grow() {
+                    return Err((value, e));
+                }
+                Ok(self
+                    .try_insert(value)
+                    .map_err(|_| ())
+                    .expect("inserting will always succeed after reserving additional space"))
+            }
         }
     }
 
-    /// Insert the value returned by `create` into the arena, allocating more capacity if necessary.
-    /// `create` is called with the new value's associated index, allowing values that know their own index.
-    ///
-    /// The new value's associated index in the arena is returned.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use generational_arena::{Arena, Index};
-    ///
-    /// let mut arena = Arena::new();
-    ///
-    /// let idx = arena.insert_with(|idx| (42, idx));
-    /// assert_eq!(arena[idx].0, 42);
-    /// assert_eq!(arena[idx].1, idx);
-    /// ```
-    #[inline]
-    pub fn insert_with(&mut self, create: impl FnOnce(Index) -> T) -> Index {
-        match self.try_insert_with(create) {
-            Ok(i) => i,
-            Err(create) => self.insert_with_slow_path(create),
-        }
+    #[inline(never)]
+    fn insert_slow_path(&mut self, value: T) -> K {
+        self.grow();
+        self.try_insert(value)
+            .map_err(|_| ())
+            .expect("inserting will always succeed after reserving additional space")
     }
 
     #[inline(never)]
-    fn insert_slow_path(&mut self, value: T) -> Index {
-        let len = if self.capacity() == 0 {
+    fn insert_with_slow_path(&mut self, create: impl FnOnce(K) -> T) -> K {
+        self.grow();
+        self.try_insert_with(create)
+            .map_err(|_| ())
+            .expect("inserting will always succeed after reserving additional space")
+    }
+
+    /// The number of additional slots that the slow insertion paths allocate:
+    /// the arena's capacity is doubled, without exceeding the number of slots
+    /// that `K` can address.
+    fn growth_amount(&self) -> usize {
+        // Growing adds the slots reserved by `reserve_index_shared` first.
+        let slots = self.items.len() + self.reserved.load(Ordering::Relaxed);
+        let len = if slots == 0 {
             // `drain()` sets the capacity to 0 and if the capacity is 0, the
             // next `try_insert() `will refer to an out-of-range index because
             // the next `reserve()` does not add element, resulting in a panic.
@@ -519,21 +2486,50 @@ impl<T> Arena<T> {
             // returns an iterator that borrows `self` mutably.
             1
         } else {
-            self.items.len()
+            slots
         };
-        self.reserve(len);
-        self.try_insert(value)
-            .map_err(|_| ())
-            .expect("inserting will always succeed after reserving additional space")
+        let remaining = K::MAX_INDEX
+            .checked_sub(slots)
+            .map_or(0, |r| r.saturating_add(1));
+        cmp::min(len, remaining)
     }
 
-    #[inline(never)]
-    fn insert_with_slow_path(&mut self, create: impl FnOnce(Index) -> T) -> Index {
-        let len = self.items.len();
-        self.reserve(len);
-        self.try_insert_with(create)
-            .map_err(|_| ())
-            .expect("inserting will always succeed after reserving additional space")
+    fn grow(&mut self) {
+        self.flush_reserved();
+        let additional = self.growth_amount();
+        assert!(additional > 0, "arena has no more indices available");
+        self.reserve(additional);
+    }
+
+    fn try_grow(&mut self) -> Result<(), TryReserveError> {
+        let additional = self.growth_amount();
+        if additional == 0 {
+            return Err(TryReserveError::CapacityOverflow);
+        }
+        self.try_reserve(additional)
+    }
+
+    /// Returns the generation that comes after `generation`, or `None` if
+    /// `generation` is the largest generation that `K` can hold, in which case
+    /// the slot must be retired.
+    fn bump_generation(generation: u64) -> Option<u64> {
+        if generation < K::MAX_GENERATION {
+            Some(generation + 1)
+        } else {
+            None
+        }
+    }
+
+    /// The generation that the next element inserted into `entry`'s slot will
+    /// get, once it is free, or `None` if the slot is or must be retired.
+    fn next_generation(entry: &Entry<T>) -> Option<u64> {
+        match *entry {
+            Entry::Free { generation, .. } => Some(generation),
+            Entry::Occupied { generation, .. } | Entry::Reserved { generation } => {
+                Self::bump_generation(generation)
+            }
+            Entry::Retired => None,
+        }
     }
 
     /// Remove the element at index `i` from the arena.
@@ -541,6 +2537,11 @@ impl<T> Arena<T> {
     /// If the element at index `i` is still in the arena, then it is
     /// returned. If it is not in the arena, then `None` is returned.
     ///
+    /// If the element's generation is the largest generation that `K` can
+    /// represent, its slot is retired instead of being made available for
+    /// reuse, so that stale indices to it can never become valid again. See
+    /// `retired_count`.
+    ///
     /// # Examples
     ///
     /// ```
@@ -552,30 +2553,121 @@ impl<T> Arena<T> {
     /// assert_eq!(arena.remove(idx), Some(42));
     /// assert_eq!(arena.remove(idx), None);
     /// ```
-    pub fn remove(&mut self, i: Index) -> Option<T> {
-        if i.index >= self.items.len() {
-            return None;
-        }
+    pub fn remove(&mut self, i: K) -> Option<T> {
+        self.try_remove(i).ok()
+    }
+
+    /// Remove the element at index `i` from the arena, or describe why there
+    /// is no such element.
+    ///
+    /// This is like `remove`, but returns a `LookupError` instead of `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, LookupError};
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// assert_eq!(arena.try_remove(idx), Ok(42));
+    /// assert_eq!(arena.try_remove(idx), Err(LookupError::Vacant { index: 0 }));
+    /// ```
+    pub fn try_remove(&mut self, i: K) -> Result<T, LookupError> {
+        let i = i.into_index();
+        self.lookup(i)?;
+        Ok(self.remove_occupied(i))
+    }
 
+    /// Remove the element at index `i`, which `lookup` has found.
+    pub(crate) fn remove_occupied(&mut self, i: Index) -> T {
+        let freed = match Self::bump_generation(i.generation()) {
+            Some(generation) => Entry::Free {
+                next_free: None,
+                generation,
+            },
+            None => Entry::Retired,
+        };
+        self.touch(i.index);
+        let entry = mem::replace(&mut self.items[i.index], freed);
         match self.items[i.index] {
-            Entry::Occupied { generation, .. } if i.generation == generation => {
-                let entry = mem::replace(
-                    &mut self.items[i.index],
-                    Entry::Free { next_free: self.free_list_head },
-                );
-                self.generation += 1;
-                self.free_list_head = Some(i.index);
-                self.len -= 1;
+            Entry::Free { .. } => self.release(i.index),
+            _ => self.retired += 1,
+        }
+        self.len -= 1;
+        self.record(i, Changes::remove);
 
-                match entry {
-                    Entry::Occupied { generation: _, value } => Some(value),
-                    _ => unreachable!(),
-                }
+        match entry {
+            Entry::Occupied { generation: _, value } => value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Check that `i` refers to an element in the arena, and return its
+    /// generation.
+    pub(crate) fn lookup(&self, i: Index) -> Result<u64, LookupError> {
+        match self.items.get(i.index) {
+            None => Err(LookupError::OutOfBounds {
+                index: i.index,
+                capacity: self.items.len(),
+            }),
+            Some(Entry::Occupied { generation, .. }) if *generation == i.generation() => {
+                Ok(*generation)
+            }
+            Some(Entry::Reserved { generation }) if *generation == i.generation() => {
+                Err(LookupError::Reserved { index: i.index })
+            }
+            Some(Entry::Occupied { generation, .. }) | Some(Entry::Reserved { generation }) => {
+                Err(LookupError::StaleGeneration {
+                    expected: i.generation(),
+                    found: *generation,
+                })
+            }
+            Some(Entry::Free { .. }) | Some(Entry::Retired) => {
+                Err(LookupError::Vacant { index: i.index })
             }
-            _ => None,
         }
     }
 
+    /// Give the element at index `i` a new index, keeping it in place.
+    ///
+    /// The element's generation is bumped, so `i` and all its copies become
+    /// stale, while the returned index refers to the same element in the same
+    /// slot.
+    ///
+    /// Returns `None` if `i` is not in the arena, or if the element's
+    /// generation is the largest generation that `K` can represent, in which
+    /// case the element keeps its index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let old = arena.insert(42);
+    ///
+    /// let new = arena.reissue(old).unwrap();
+    /// assert!(!arena.contains(old));
+    /// assert_eq!(arena[new], 42);
+    /// assert_eq!(arena.reissue(old), None);
+    /// ```
+    pub fn reissue(&mut self, i: K) -> Option<K> {
+        let i = i.into_index();
+        self.lookup(i).ok()?;
+        self.touch(i.index);
+        let new = match self.items.get_mut(i.index) {
+            Some(Entry::Occupied { generation, .. }) if *generation == i.generation() => {
+                *generation = Self::bump_generation(*generation)?;
+                Index::new(i.index, *generation)
+            }
+            _ => return None,
+        };
+        self.record(i, Changes::remove);
+        self.record(new, Changes::add);
+        Some(K::from_index(new))
+    }
+
     /// Retains only the elements specified by the predicate.
     ///
     /// In other words, remove all indices such that `predicate(index, &value)` returns `false`.
@@ -594,27 +2686,169 @@ impl<T> Arena<T> {
     /// assert_eq!(crew_members.next(), Some("Alexander Smollett"));
     /// assert!(crew_members.next().is_none());
     /// ```
-    pub fn retain(&mut self, mut predicate: impl FnMut(Index, &mut T) -> bool) {
+    pub fn retain(&mut self, mut predicate: impl FnMut(K, &mut T) -> bool) {
+        self.touch_all();
         for i in 0..self.capacity() {
-            let remove = match &mut self.items[i] {
+            let retained = match &mut self.items[i] {
                 Entry::Occupied { generation, value } => {
-                    let index = Index {
-                        index: i,
-                        generation: *generation,
-                    };
-                    if predicate(index, value) {
-                        None
-                    } else {
-                        Some(index)
-                    }
+                    let index = Index::new(i, *generation);
+                    Some((index, predicate(K::from_index(index), value)))
                 }
 
                 _ => None,
             };
-            if let Some(index) = remove {
-                self.remove(index);
+            match retained {
+                // The predicate may have changed the retained element.
+                Some((index, true)) => self.record(index, Changes::modify),
+                Some((index, false)) => {
+                    self.remove(K::from_index(index));
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Move the elements of the arena into the lowest free slots, then release
+    /// the free slots left at the end of the arena.
+    ///
+    /// Each moved element gets a new index, and `moved(old, new)` is called
+    /// for each of them so that stored indices can be updated. The old indices
+    /// become stale; they never refer to an element again. Elements that are
+    /// not moved keep their index.
+    ///
+    /// Retired slots (see `retired_count`) cannot be reused, so they stay
+    /// where they are: elements are moved around them, into the free slots
+    /// before and after them, and the arena keeps its slots up to the last
+    /// retired one. Quarantined slots (see `set_quarantine_len`) are released
+    /// and may be reused right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idxs: Vec<_> = (0..10).map(|i| arena.insert(i)).collect();
+    /// for &i in &idxs[..8] {
+    ///     arena.remove(i);
+    /// }
+    ///
+    /// let mut handles = vec![idxs[8], idxs[9]];
+    /// arena.compact_with(|old, new| {
+    ///     for handle in handles.iter_mut() {
+    ///         if *handle == old {
+    ///             *handle = new;
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(arena.capacity(), 2);
+    /// assert_eq!(arena[handles[0]] + arena[handles[1]], 17);
+    /// assert!(arena.get(idxs[9]).is_none());
+    /// ```
+    ///
+    /// Retired slots stay in place, and elements move past them:
+    ///
+    /// ```
+    /// use generational_arena::{ArenaKey, CompactArena, CompactIndex};
+    ///
+    /// let max = CompactIndex::MAX_GENERATION;
+    /// let mut arena = CompactArena::with_capacity_and_generation(4, max - 1);
+    /// let idxs: Vec<_> = (0..4).map(|i| arena.insert(i)).collect();
+    ///
+    /// // Slot 1 is retired once its generation cannot be bumped anymore.
+    /// arena.remove(idxs[1]);
+    /// let idx = arena.insert(4);
+    /// arena.remove(idx);
+    /// assert_eq!(arena.retired_count(), 1);
+    ///
+    /// // Slot 0 is free, so the element in slot 3 moves past slot 1 into it.
+    /// arena.remove(idxs[0]);
+    /// let mut moves = Vec::new();
+    /// arena.compact_with(|old, new| moves.push((old, new)));
+    ///
+    /// assert_eq!(moves.len(), 1);
+    /// assert_eq!(moves[0].0, idxs[3]);
+    /// assert_eq!(moves[0].1.into_raw_parts().0, 0);
+    /// assert_eq!(arena.capacity(), 3);
+    /// assert_eq!(arena.retired_count(), 1);
+    /// assert_eq!(arena[idxs[2]], 2);
+    /// ```
+    pub fn compact_with(&mut self, mut moved: impl FnMut(K, K)) {
+        self.touch_all();
+        // Quarantined slots are free slots like any other from here on, and
+        // the free list is rebuilt once the elements have moved.
+        self.quarantine.clear();
+        self.clear_free_list();
+        let mut lo = 0;
+        let mut hi = self.items.len();
+        loop {
+            while lo < hi && !matches!(self.items[lo], Entry::Free { .. }) {
+                lo += 1;
+            }
+            while lo < hi && !matches!(self.items[hi - 1], Entry::Occupied { .. }) {
+                hi -= 1;
+            }
+            if lo == hi {
+                break;
+            }
+            hi -= 1;
+
+            let to_generation = match self.items[lo] {
+                Entry::Free { generation, .. } => generation,
+                _ => unreachable!(),
+            };
+            let freed = match Self::next_generation(&self.items[hi]) {
+                Some(generation) => Entry::Free {
+                    next_free: None,
+                    generation,
+                },
+                None => Entry::Retired,
+            };
+            let entry = mem::replace(&mut self.items[hi], freed);
+            if let Entry::Occupied { generation, value } = entry {
+                self.items[lo] = Entry::Occupied {
+                    generation: to_generation,
+                    value,
+                };
+                let (old, new) = (Index::new(hi, generation), Index::new(lo, to_generation));
+                self.record(old, Changes::remove);
+                self.record(new, Changes::add);
+                moved(K::from_index(old), K::from_index(new));
             }
+            lo += 1;
         }
+
+        self.shrink_to_fit();
+        self.rebuild_free_list();
+    }
+
+    /// Move the elements of the arena into the lowest free slots, then release
+    /// the free slots left at the end of the arena.
+    ///
+    /// Returns the `(old, new)` index pairs of the elements that moved. See
+    /// `compact_with` for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let a = arena.insert("a");
+    /// let b = arena.insert("b");
+    /// arena.remove(a);
+    ///
+    /// let remap = arena.compact();
+    /// assert_eq!(remap.len(), 1);
+    /// assert_eq!(remap[0].0, b);
+    /// assert_eq!(arena[remap[0].1], "b");
+    /// assert_eq!(arena.capacity(), 1);
+    /// ```
+    pub fn compact(&mut self) -> Vec<(K, K)> {
+        let mut remap = Vec::new();
+        self.compact_with(|old, new| remap.push((old, new)));
+        remap
     }
 
     /// Is the element at index `i` in the arena?
@@ -633,7 +2867,7 @@ impl<T> Arena<T> {
     /// arena.remove(idx);
     /// assert!(!arena.contains(idx));
     /// ```
-    pub fn contains(&self, i: Index) -> bool {
+    pub fn contains(&self, i: K) -> bool {
         self.get(i).is_some()
     }
 
@@ -654,12 +2888,13 @@ impl<T> Arena<T> {
     /// arena.remove(idx);
     /// assert!(arena.get(idx).is_none());
     /// ```
-    pub fn get(&self, i: Index) -> Option<&T> {
+    pub fn get(&self, i: K) -> Option<&T> {
+        let i = i.into_index();
         match self.items.get(i.index) {
             Some(Entry::Occupied {
                 generation,
                 value,
-            }) if *generation == i.generation => Some(value),
+            }) if *generation == i.generation() => Some(value),
             _ => None,
         }
     }
@@ -667,28 +2902,130 @@ impl<T> Arena<T> {
     /// Get an exclusive reference to the element at index `i` if it is in the
     /// arena.
     ///
-    /// If the element at index `i` is not in the arena, then `None` is returned.
+    /// If the element at index `i` is not in the arena, then `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// *arena.get_mut(idx).unwrap() += 1;
+    /// assert_eq!(arena.remove(idx), Some(43));
+    /// assert!(arena.get_mut(idx).is_none());
+    /// ```
+    pub fn get_mut(&mut self, i: K) -> Option<&mut T> {
+        self.try_get_mut(i).ok()
+    }
+
+    /// Get a shared reference to the element at index `i`, or describe why
+    /// there is no such element.
+    ///
+    /// This is like `get`, but returns a `LookupError` instead of `None`, to
+    /// tell apart indices that are out of bounds, that point to a vacant slot,
+    /// and that are stale.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, LookupError};
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    /// assert_eq!(arena.try_get(idx), Ok(&42));
+    ///
+    /// arena.remove(idx);
+    /// assert_eq!(arena.try_get(idx), Err(LookupError::Vacant { index: 0 }));
+    ///
+    /// arena.insert(43);
+    /// assert_eq!(
+    ///     arena.try_get(idx),
+    ///     Err(LookupError::StaleGeneration { expected: 0, found: 1 }),
+    /// );
+    /// ```
+    pub fn try_get(&self, i: K) -> Result<&T, LookupError> {
+        let i = i.into_index();
+        self.lookup(i)?;
+        Ok(self.occupied(i))
+    }
+
+    /// Get the element at index `i`, which `lookup` has found.
+    pub(crate) fn occupied(&self, i: Index) -> &T {
+        match &self.items[i.index] {
+            Entry::Occupied { value, .. } => value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Get an exclusive reference to the element at index `i`, or describe why
+    /// there is no such element.
+    ///
+    /// This is like `get_mut`, but returns a `LookupError` instead of `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, Index, LookupError};
+    ///
+    /// let mut arena = Arena::with_capacity(1);
+    /// let idx = arena.insert(42);
+    /// *arena.try_get_mut(idx).unwrap() += 1;
+    /// assert_eq!(arena[idx], 43);
+    ///
+    /// let other = Index::from_raw_parts(5, 0);
+    /// assert_eq!(
+    ///     arena.try_get_mut(other),
+    ///     Err(LookupError::OutOfBounds { index: 5, capacity: 1 }),
+    /// );
+    /// ```
+    pub fn try_get_mut(&mut self, i: K) -> Result<&mut T, LookupError> {
+        let i = i.into_index();
+        self.lookup(i)?;
+        Ok(self.occupied_mut(i))
+    }
+
+    /// Get the element at index `i`, which `lookup` has found, mutably.
+    pub(crate) fn occupied_mut(&mut self, i: Index) -> &mut T {
+        self.touch(i.index);
+        self.record(i, Changes::modify);
+        match &mut self.items[i.index] {
+            Entry::Occupied { value, .. } => value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Get the entry for index `i`, for in-place manipulation.
+    ///
+    /// The entry is occupied if `i` refers to an element in the arena, and
+    /// vacant if it is stale or comes from another arena. A vacant entry can be
+    /// used to insert a new element, which gets a new index.
     ///
     /// # Examples
     ///
     /// ```
     /// use generational_arena::Arena;
+    /// use generational_arena::entry::Entry;
     ///
     /// let mut arena = Arena::new();
     /// let idx = arena.insert(42);
     ///
-    /// *arena.get_mut(idx).unwrap() += 1;
-    /// assert_eq!(arena.remove(idx), Some(43));
-    /// assert!(arena.get_mut(idx).is_none());
+    /// match arena.entry(idx) {
+    ///     Entry::Occupied(mut entry) => *entry.get_mut() += 1,
+    ///     Entry::Vacant(_) => unreachable!(),
+    /// }
+    /// assert_eq!(arena[idx], 43);
+    ///
+    /// arena.remove(idx);
+    /// let new_idx = match arena.entry(idx) {
+    ///     Entry::Occupied(_) => unreachable!(),
+    ///     Entry::Vacant(entry) => entry.insert(0),
+    /// };
+    /// assert_eq!(arena[new_idx], 0);
     /// ```
-    pub fn get_mut(&mut self, i: Index) -> Option<&mut T> {
-        match self.items.get_mut(i.index) {
-            Some(Entry::Occupied {
-                generation,
-                value,
-            }) if *generation == i.generation => Some(value),
-            _ => None,
-        }
+    pub fn entry(&mut self, i: K) -> entry::Entry<'_, T, K, P> {
+        entry::Entry::new(self, i)
     }
 
     /// Get a pair of exclusive references to the elements at index `i1` and `i2` if it is in the
@@ -720,22 +3057,29 @@ impl<T> Arena<T> {
     /// assert_eq!(arena[idx1], 3);
     /// assert_eq!(arena[idx2], 4);
     /// ```
-    pub fn get2_mut(&mut self, i1: Index, i2: Index) -> (Option<&mut T>, Option<&mut T>) {
+    pub fn get2_mut(&mut self, i1: K, i2: K) -> (Option<&mut T>, Option<&mut T>) {
+        let (i1, i2) = (i1.into_index(), i2.into_index());
         let len = self.items.len();
 
         if i1.index == i2.index {
-            assert!(i1.generation != i2.generation);
+            assert!(i1.generation() != i2.generation());
 
-            if i1.generation > i2.generation {
-                return (self.get_mut(i1), None);
+            if i1.generation() > i2.generation() {
+                return (self.get_mut(K::from_index(i1)), None);
             }
-            return (None, self.get_mut(i2));
+            return (None, self.get_mut(K::from_index(i2)));
         }
 
         if i1.index >= len {
-            return (None, self.get_mut(i2));
+            return (None, self.get_mut(K::from_index(i2)));
         } else if i2.index >= len {
-            return (self.get_mut(i1), None);
+            return (self.get_mut(K::from_index(i1)), None);
+        }
+        for i in [i1, i2] {
+            if self.lookup(i).is_ok() {
+                self.touch(i.index);
+                self.record(i, Changes::modify);
+            }
         }
 
         let (raw_item1, raw_item2) = {
@@ -751,7 +3095,7 @@ impl<T> Arena<T> {
             Entry::Occupied {
                 generation,
                 value,
-            } if *generation == i1.generation => Some(value),
+            } if *generation == i1.generation() => Some(value),
             _ => None,
         };
 
@@ -759,13 +3103,212 @@ impl<T> Arena<T> {
             Entry::Occupied {
                 generation,
                 value,
-            } if *generation == i2.generation => Some(value),
+            } if *generation == i2.generation() => Some(value),
             _ => None,
         };
 
         (item1, item2)
     }
 
+    /// Get exclusive references to the `N` elements at the given indices, all
+    /// at once.
+    ///
+    /// Unlike `get2_mut`, this never panics: if any index does not refer to an
+    /// element in the arena, or if two indices refer to the same slot, an
+    /// error is returned instead. Errors identify indices by their position in
+    /// `indices`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, GetManyError};
+    ///
+    /// let mut arena = Arena::new();
+    /// let a = arena.insert(1);
+    /// let b = arena.insert(2);
+    /// let c = arena.insert(3);
+    ///
+    /// let [x, y, z] = arena.get_many_mut([c, a, b]).unwrap();
+    /// std::mem::swap(x, y);
+    /// *z += 10;
+    /// assert_eq!((arena[a], arena[b], arena[c]), (3, 12, 1));
+    ///
+    /// assert_eq!(
+    ///     arena.get_many_mut([a, b, a]).unwrap_err(),
+    ///     GetManyError::Duplicate { first: 0, second: 2 },
+    /// );
+    ///
+    /// arena.remove(b);
+    /// assert_eq!(
+    ///     arena.get_many_mut([a, b, c]).unwrap_err(),
+    ///     GetManyError::NotFound { position: 1 },
+    /// );
+    /// ```
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        indices: [K; N],
+    ) -> Result<[&mut T; N], GetManyError> {
+        let indices = indices.map(K::into_index);
+        let values = self
+            .get_many_slots_mut(indices.map(|i| i.index), |position, generation| {
+                generation == indices[position].generation()
+            })?;
+        Ok(values.map(|(value, _)| value))
+    }
+
+    /// Like `get_many_mut`, but without sorting the indices to find
+    /// duplicates.
+    ///
+    /// Instead, the indices must be given in strictly ascending order of
+    /// their slots. This crate does not use `unsafe` code, so breaking that
+    /// requirement can never hand out two references to the same element:
+    /// it is checked with a single pass over the indices, and this method
+    /// panics if it does not hold. Stale indices are still reported as
+    /// errors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the indices are not in strictly ascending order of their
+    /// slots.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, GetManyError};
+    ///
+    /// let mut arena = Arena::new();
+    /// let a = arena.insert(1);
+    /// let b = arena.insert(2);
+    /// let c = arena.insert(3);
+    ///
+    /// let [x, z] = arena.get_many_unchecked_mut([a, c]).unwrap();
+    /// std::mem::swap(x, z);
+    /// assert_eq!((arena[a], arena[c]), (3, 1));
+    ///
+    /// arena.remove(b);
+    /// assert_eq!(
+    ///     arena.get_many_unchecked_mut([a, b]).unwrap_err(),
+    ///     GetManyError::NotFound { position: 1 },
+    /// );
+    /// ```
+    pub fn get_many_unchecked_mut<const N: usize>(
+        &mut self,
+        indices: [K; N],
+    ) -> Result<[&mut T; N], GetManyError> {
+        let indices = indices.map(K::into_index);
+        for (position, i) in indices.iter().enumerate() {
+            if self.lookup(*i).is_err() {
+                return Err(GetManyError::NotFound { position });
+            }
+        }
+        let mut order = [0; N];
+        for (position, o) in order.iter_mut().enumerate() {
+            *o = position;
+        }
+        let values = self.split_slots_mut(indices.map(|i| i.index), &order);
+        Ok(values.map(|(value, _)| value))
+    }
+
+    /// Like `get_many_mut`, but given slot indices without generations.
+    ///
+    /// Returns exclusive references to the elements along with their matching
+    /// indices. As with `get_unknown_gen_mut`, you should use `get_many_mut`
+    /// instead most of the time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let a = arena.insert(1);
+    /// let b = arena.insert(2);
+    ///
+    /// let [(x, i), (y, j)] = arena.get_many_unknown_gen_mut([1, 0]).unwrap();
+    /// assert_eq!((*x, *y), (2, 1));
+    /// assert_eq!((i, j), (b, a));
+    ///
+    /// assert!(arena.get_many_unknown_gen_mut([0, 0]).is_err());
+    /// assert!(arena.get_many_unknown_gen_mut([0, 7]).is_err());
+    /// ```
+    pub fn get_many_unknown_gen_mut<const N: usize>(
+        &mut self,
+        slots: [usize; N],
+    ) -> Result<[(&mut T, K); N], GetManyError> {
+        let values = self.get_many_slots_mut(slots, |_, _| true)?;
+        Ok(values.map(|(value, i)| (value, K::from_index(i))))
+    }
+
+    /// Get exclusive references to the elements in the given slots, along
+    /// with their indices, if every slot is occupied by an element that
+    /// `is_live(position, generation)` accepts and no slot is given twice.
+    fn get_many_slots_mut<const N: usize>(
+        &mut self,
+        slots: [usize; N],
+        is_live: impl Fn(usize, u64) -> bool,
+    ) -> Result<[(&mut T, Index); N], GetManyError> {
+        for (position, &slot) in slots.iter().enumerate() {
+            match self.items.get(slot) {
+                Some(Entry::Occupied { generation, .. }) if is_live(position, *generation) => {}
+                _ => return Err(GetManyError::NotFound { position }),
+            }
+        }
+
+        // Visit the slots in ascending order, so that the references can be
+        // split off the front of the remaining items one after the other.
+        let mut order = [0; N];
+        for (position, o) in order.iter_mut().enumerate() {
+            *o = position;
+        }
+        order.sort_unstable_by_key(|&position| (slots[position], position));
+        for pair in order.windows(2) {
+            if slots[pair[0]] == slots[pair[1]] {
+                return Err(GetManyError::Duplicate {
+                    first: pair[0],
+                    second: pair[1],
+                });
+            }
+        }
+
+        Ok(self.split_slots_mut(slots, &order))
+    }
+
+    /// Get exclusive references to the elements in the given occupied slots,
+    /// along with their indices, visiting them in `order`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` does not visit the slots in strictly ascending order.
+    fn split_slots_mut<const N: usize>(
+        &mut self,
+        slots: [usize; N],
+        order: &[usize; N],
+    ) -> [(&mut T, Index); N] {
+        assert!(
+            order.windows(2).all(|pair| slots[pair[0]] < slots[pair[1]]),
+            "indices must refer to slots in strictly ascending order"
+        );
+        for &slot in &slots {
+            self.touch(slot);
+            if let Entry::Occupied { generation, .. } = self.items[slot] {
+                self.record(Index::new(slot, generation), Changes::modify);
+            }
+        }
+        let mut values = [(); N].map(|_| None);
+        let mut rest = &mut self.items[..];
+        let mut offset = 0;
+        for &position in order {
+            let (_, tail) = mem::take(&mut rest).split_at_mut(slots[position] - offset);
+            let (entry, tail) = tail.split_first_mut().unwrap();
+            rest = tail;
+            offset = slots[position] + 1;
+            if let Entry::Occupied { generation, value } = entry {
+                values[position] = Some((value, Index::new(slots[position], *generation)));
+            }
+        }
+        values.map(|value| value.expect("every slot was checked to be occupied"))
+    }
+
     /// Get the length of this arena.
     ///
     /// The length is the number of elements the arena holds.
@@ -839,11 +3382,91 @@ impl<T> Arena<T> {
         self.items.len()
     }
 
+    /// Get the number of retired slots in this arena.
+    ///
+    /// When an element whose generation is the largest generation that the
+    /// arena's index type can represent (see `ArenaKey::MAX_GENERATION`) is
+    /// removed, its slot is retired: it is never used again, so that stale
+    /// indices to it can never become valid again. Retired slots still count
+    /// towards the arena's `capacity`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, ArenaKey, Index};
+    ///
+    /// let max = Index::MAX_GENERATION;
+    /// let mut arena = Arena::<u32>::with_capacity_and_generation(1, max);
+    /// let idx = arena.insert(1);
+    /// assert_eq!(arena.retired_count(), 0);
+    ///
+    /// arena.remove(idx);
+    /// assert_eq!(arena.retired_count(), 1);
+    ///
+    /// // The retired slot is never reused.
+    /// assert!(arena.try_insert(2).is_err());
+    /// ```
+    pub fn retired_count(&self) -> usize {
+        self.retired
+    }
+
+    /// Get the length of this arena's quarantine.
+    ///
+    /// See `set_quarantine_len`.
+    pub fn quarantine_len(&self) -> usize {
+        self.quarantine_len
+    }
+
+    /// Keep the slots of removed elements in quarantine for `len` further
+    /// removals before reusing them.
+    ///
+    /// While a slot is in quarantine, stale indices to the element that was
+    /// removed from it point to a vacant slot, rather than to a newer element,
+    /// which makes use-after-free bugs easier to catch with `try_get`. The
+    /// price is that up to `len` free slots cannot be used by insertions: the
+    /// arena grows instead.
+    ///
+    /// This is meant for debugging, for example only in builds with
+    /// `debug_assertions` enabled. A length of 0, the default, disables the
+    /// quarantine. Reducing the length releases the oldest quarantined slots
+    /// for reuse. `clear`, `drain` and `compact` release every quarantined
+    /// slot, while `reserve` adds the new slots for immediate reuse. The
+    /// quarantine length is kept by `Clone`, but is not serialized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, LookupError};
+    ///
+    /// let mut arena = Arena::with_capacity(2);
+    /// arena.set_quarantine_len(1);
+    /// let a = arena.insert("a");
+    /// arena.remove(a);
+    ///
+    /// // The slot of `a` is in quarantine, so this uses another slot.
+    /// let b = arena.insert("b");
+    /// assert_eq!(arena.try_get(a), Err(LookupError::Vacant { index: 0 }));
+    ///
+    /// // Removing `b` puts its slot in quarantine and releases the slot of `a`.
+    /// arena.remove(b);
+    /// let c = arena.insert("c");
+    /// assert_eq!(c.into_raw_parts().0, 0);
+    /// ```
+    pub fn set_quarantine_len(&mut self, len: usize) {
+        self.quarantine_len = len;
+        while self.quarantine.len() > len {
+            let oldest = self.quarantine.pop_front().unwrap();
+            self.push_free(oldest);
+        }
+    }
+
     /// Allocate space for `additional_capacity` more elements in the arena.
     ///
     /// # Panics
     ///
-    /// Panics if this causes the capacity to overflow.
+    /// Panics if this causes the capacity to overflow, or to exceed the number
+    /// of slots that the arena's index type can address (see
+    /// `ArenaKey::MAX_INDEX`).
     ///
     /// # Examples
     ///
@@ -856,22 +3479,123 @@ impl<T> Arena<T> {
     /// # let _: Arena<usize> = arena;
     /// ```
     pub fn reserve(&mut self, additional_capacity: usize) {
-        self.items.reserve_exact(additional_capacity);
+        self.flush_reserved();
+        if additional_capacity == 0 {
+            return;
+        }
         let start = self.items.len();
-        let end = self.items.len() + additional_capacity;
-        let old_head = self.free_list_head;
-        self.items.extend((start..end).map(|i| {
-            if i == end - 1 {
-                Entry::Free {
-                    next_free: old_head,
-                }
-            } else {
-                Entry::Free {
-                    next_free: Some(i + 1),
-                }
-            }
+        let end = start
+            .checked_add(additional_capacity)
+            .expect("capacity overflow");
+        assert!(
+            end - 1 <= K::MAX_INDEX,
+            "capacity exceeds the number of slots addressable by the arena's index type"
+        );
+        self.items.reserve_exact(additional_capacity);
+        let generation = self.generation;
+        self.items.extend((start..end).map(|_| Entry::Free {
+            next_free: None,
+            generation,
         }));
-        self.free_list_head = Some(start);
+        // New slots are reused in ascending order, before the slots that were
+        // already free under `Lifo`, and after them otherwise.
+        match P::KIND {
+            ReuseKind::Lifo => (start..end).rev().for_each(|i| self.push_free(i)),
+            ReuseKind::Fifo | ReuseKind::LowestFirst => (start..end).for_each(|i| self.push_free(i)),
+        }
+    }
+
+    /// Try to allocate space for `additional_capacity` more elements in the
+    /// arena.
+    ///
+    /// This is like `reserve`, but returns an error instead of panicking or
+    /// aborting if the capacity would overflow or exceed the number of slots
+    /// that the arena's index type can address, or if allocation fails. On
+    /// error, the arena is left unchanged: the slots reserved by
+    /// `reserve_index_shared` are only added once the allocation succeeds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, TryReserveError};
+    ///
+    /// let mut arena = Arena::with_capacity(10);
+    /// arena.try_reserve(5).unwrap();
+    /// assert_eq!(arena.capacity(), 15);
+    ///
+    /// assert_eq!(arena.try_reserve(usize::MAX), Err(TryReserveError::CapacityOverflow));
+    /// assert_eq!(arena.capacity(), 15);
+    /// # let _: Arena<usize> = arena;
+    /// ```
+    pub fn try_reserve(&mut self, additional_capacity: usize) -> Result<(), TryReserveError> {
+        // The slots reserved by `reserve_index_shared` are added as well, but
+        // only once the space for them has been allocated.
+        let reserved = *self.reserved.get_mut();
+        let end = self
+            .items
+            .len()
+            .checked_add(reserved)
+            .and_then(|len| len.checked_add(additional_capacity))
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        if additional_capacity > 0 && end - 1 > K::MAX_INDEX {
+            return Err(TryReserveError::CapacityOverflow);
+        }
+        self.items
+            .try_reserve_exact(end - self.items.len())
+            .map_err(TryReserveError::AllocError)?;
+        // The space is already allocated, so this cannot fail.
+        self.reserve(additional_capacity);
+        Ok(())
+    }
+
+    /// Release the free slots at the end of the arena, keeping a capacity of
+    /// at least `min_capacity`.
+    ///
+    /// No element is moved, so all indices stay valid; use `compact` to
+    /// release the free slots in the middle of the arena as well. If the
+    /// capacity is already lower than `min_capacity`, this does nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::with_capacity(10);
+    /// let idx = arena.insert(42);
+    ///
+    /// arena.shrink_to(4);
+    /// assert_eq!(arena.capacity(), 4);
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        if min_capacity >= self.items.len() {
+            return;
+        }
+        self.truncate_free_slots(min_capacity);
+        self.items.shrink_to_fit();
+    }
+
+    /// Release all the free slots at the end of the arena.
+    ///
+    /// No element is moved, so all indices stay valid. See `shrink_to`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::with_capacity(10);
+    /// let a = arena.insert(1);
+    /// let b = arena.insert(2);
+    /// arena.insert(3);
+    /// arena.remove(a);
+    ///
+    /// arena.shrink_to_fit();
+    /// assert_eq!(arena.capacity(), 3);
+    /// assert_eq!(arena[b], 2);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_to(0);
     }
 
     /// Iterate over shared references to the elements in this arena.
@@ -894,10 +3618,11 @@ impl<T> Arena<T> {
     ///     println!("{} is at index {:?}", value, idx);
     /// }
     /// ```
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T, K> {
         Iter {
             len: self.len,
             inner: self.items.iter().enumerate(),
+            key: PhantomData,
         }
     }
 
@@ -921,13 +3646,54 @@ impl<T> Arena<T> {
     ///     *value += 5;
     /// }
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, K> {
+        self.touch_all();
+        self.record_all(Changes::modify);
         IterMut {
             len: self.len,
             inner: self.items.iter_mut().enumerate(),
+            key: PhantomData,
         }
     }
 
+    /// Iterate over exclusive references to the elements in this arena, while
+    /// reserving indices with the returned `Reserver`.
+    ///
+    /// This allows recording insertions into a `Commands` buffer while
+    /// iterating, see `Commands` for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.insert(1);
+    ///
+    /// let mut children = Vec::new();
+    /// let (iter, reserver) = arena.iter_mut_with_reserver();
+    /// for (_, value) in iter {
+    ///     *value += 1;
+    ///     children.push((reserver.reserve_index(), *value * 10));
+    /// }
+    ///
+    /// for (idx, value) in children {
+    ///     assert_eq!(arena.fill(idx, value), Ok(()));
+    /// }
+    /// assert_eq!(arena.len(), 2);
+    /// ```
+    pub fn iter_mut_with_reserver(&mut self) -> (IterMut<'_, T, K>, Reserver<'_, K>) {
+        self.touch_all();
+        self.record_all(Changes::modify);
+        let reserver = Reserver::new(&self.reserved, self.items.len(), self.generation);
+        let iter = IterMut {
+            len: self.len,
+            inner: self.items.iter_mut().enumerate(),
+            key: PhantomData,
+        };
+        (iter, reserver)
+    }
+
     /// Iterate over elements of the arena and remove them.
     ///
     /// Yields pairs of `(Index, T)` items.
@@ -953,18 +3719,48 @@ impl<T> Arena<T> {
     /// assert!(arena.get(idx_1).is_none());
     /// assert!(arena.get(idx_2).is_none());
     /// ```
-    pub fn drain(&mut self) -> Drain<T> {
+    pub fn drain(&mut self) -> Drain<'_, T, K> {
+        self.flush_reserved();
+        self.touch_all();
+        self.record_all(Changes::remove);
         let old_len = self.len;
-        if !self.is_empty() {
-            // Increment generation, but if there are no elements, do nothing to
-            // avoid unnecessary incrementing generation.
-            self.generation += 1;
-        }
-        self.free_list_head = None;
+        // Slots whose generations have run out stay retired, like with
+        // `clear`, so the slots up to the last of them are kept and emptied
+        // right away.
+        let kept = self
+            .items
+            .iter()
+            .rposition(|entry| Self::next_generation(entry).is_none())
+            .map_or(0, |i| i + 1);
+        let kept_entries: Vec<_> = self.items[..kept]
+            .iter_mut()
+            .map(|entry| {
+                let freed = match Self::next_generation(entry) {
+                    Some(generation) => Entry::Free {
+                        next_free: None,
+                        generation,
+                    },
+                    None => Entry::Retired,
+                };
+                mem::replace(entry, freed)
+            })
+            .collect();
+        // The other slots are dropped entirely, so make sure that the slots
+        // created afterwards start at a generation that none of the indices
+        // into the dropped slots can have.
+        self.generation = self.items[kept..]
+            .iter()
+            .filter_map(Self::next_generation)
+            .fold(self.generation, cmp::max);
+        self.quarantine.clear();
+        self.rebuild_free_list_until(kept);
         self.len = 0;
         Drain {
             len: old_len,
-            inner: self.items.drain(..).enumerate(),
+            kept: kept_entries.into_iter().enumerate(),
+            inner: self.items.drain(kept..).enumerate(),
+            offset: kept,
+            key: PhantomData,
         }
     }
 
@@ -978,12 +3774,12 @@ impl<T> Arena<T> {
     /// other kinds of bit-efficient indexing.
     ///
     /// You should use the `get` method instead most of the time.
-    pub fn get_unknown_gen(&self, i: usize) -> Option<(&T, Index)> {
+    pub fn get_unknown_gen(&self, i: usize) -> Option<(&T, K)> {
         match self.items.get(i) {
             Some(Entry::Occupied {
                 generation,
                 value,
-            }) => Some((value, Index { generation: *generation, index: i})),
+            }) => Some((value, K::from_index(Index::new(i, *generation)))),
             _ => None,
         }
     }
@@ -998,18 +3794,237 @@ impl<T> Arena<T> {
     /// other kinds of bit-efficient indexing.
     ///
     /// You should use the `get_mut` method instead most of the time.
-    pub fn get_unknown_gen_mut(&mut self, i: usize) -> Option<(&mut T, Index)> {
+    pub fn get_unknown_gen_mut(&mut self, i: usize) -> Option<(&mut T, K)> {
+        if let Some(&Entry::Occupied { generation, .. }) = self.items.get(i) {
+            self.touch(i);
+            self.record(Index::new(i, generation), Changes::modify);
+        }
         match self.items.get_mut(i) {
             Some(Entry::Occupied {
                 generation,
                 value,
-            }) => Some((value, Index { generation: *generation, index: i})),
+            }) => Some((value, K::from_index(Index::new(i, *generation)))),
+            _ => None,
+        }
+    }
+}
+
+/// The error returned by `Arena::get_many_mut`, `Arena::get_many_unchecked_mut`
+/// and `Arena::get_many_unknown_gen_mut`.
+///
+/// Indices are identified by their position in the array passed to those
+/// methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GetManyError {
+    /// The index at `position` does not refer to an element in the arena.
+    NotFound {
+        /// The position of the index.
+        position: usize,
+    },
+    /// The indices at `first` and `second` refer to the same slot.
+    Duplicate {
+        /// The position of the first of the two indices.
+        first: usize,
+        /// The position of the second of the two indices.
+        second: usize,
+    },
+}
+
+impl fmt::Display for GetManyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GetManyError::NotFound { position } => {
+                write!(f, "index at position {} is not in the arena", position)
+            }
+            GetManyError::Duplicate { first, second } => write!(
+                f,
+                "indices at positions {} and {} refer to the same slot",
+                first, second
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GetManyError {}
+
+/// The error returned by `Arena::try_get`, `Arena::try_get_mut` and
+/// `Arena::try_remove`, describing why an index does not refer to an element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LookupError {
+    /// The index's slot is beyond the end of the arena.
+    OutOfBounds {
+        /// The slot index.
+        index: usize,
+        /// The arena's capacity.
+        capacity: usize,
+    },
+    /// The index's slot is empty.
+    Vacant {
+        /// The slot index.
+        index: usize,
+    },
+    /// The index has been reserved with `Arena::reserve_index`, but its
+    /// element has not been filled in yet.
+    Reserved {
+        /// The slot index.
+        index: usize,
+    },
+    /// The index's slot holds an element or a reservation, but from another
+    /// generation: the element that the index referred to has been removed.
+    StaleGeneration {
+        /// The index's generation.
+        expected: u64,
+        /// The generation of the element in the slot.
+        found: u64,
+    },
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LookupError::OutOfBounds { index, capacity } => write!(
+                f,
+                "slot {} is out of bounds for an arena with capacity {}",
+                index, capacity
+            ),
+            LookupError::Vacant { index } => write!(f, "slot {} is vacant", index),
+            LookupError::Reserved { index } => {
+                write!(f, "slot {} is reserved but not filled yet", index)
+            }
+            LookupError::StaleGeneration { expected, found } => write!(
+                f,
+                "stale index with generation {}, but the slot holds generation {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LookupError {}
+
+/// The error returned by `Arena::apply_delta` when the arena does not match
+/// the one that the delta was created from, or the delta is invalid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeltaError {
+    /// The arena's capacity differs from that of the delta's old arena.
+    CapacityMismatch {
+        /// The capacity of the delta's old arena.
+        expected: usize,
+        /// The capacity of this arena.
+        found: usize,
+    },
+    /// The arena's length differs from that of the delta's old arena.
+    LenMismatch {
+        /// The length of the delta's old arena.
+        expected: usize,
+        /// The length of this arena.
+        found: usize,
+    },
+    /// The delta does not describe a consistent arena: its slots, counts and
+    /// free slots do not match each other once applied to this arena.
+    Invalid,
+}
+
+impl fmt::Display for DeltaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeltaError::CapacityMismatch { expected, found } => write!(
+                f,
+                "delta applies to an arena with capacity {}, but the arena has capacity {}",
+                expected, found
+            ),
+            DeltaError::LenMismatch { expected, found } => write!(
+                f,
+                "delta applies to an arena with {} elements, but the arena has {}",
+                expected, found
+            ),
+            DeltaError::Invalid => write!(f, "delta does not describe a consistent arena"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DeltaError {}
+
+/// The error returned by `Arena::try_reserve` and `Arena::try_insert_grow`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The arena cannot hold that many slots: the capacity would overflow, or
+    /// the arena's index type cannot address any more slots or has no
+    /// generations left for them.
+    CapacityOverflow,
+    /// The memory allocator failed.
+    AllocError(collections::TryReserveError),
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => {
+                f.write_str("arena capacity exceeds the limits of its index type")
+            }
+            TryReserveError::AllocError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TryReserveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TryReserveError::CapacityOverflow => None,
+            TryReserveError::AllocError(e) => Some(e),
+        }
+    }
+}
+
+/// The error returned by `Arena::insert_at`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InsertAtError {
+    /// The index's generation is older than the generation of the element in
+    /// its slot, or than the generation its slot was freed at.
+    StaleGeneration {
+        /// The index's generation.
+        generation: u64,
+        /// The oldest generation that the slot accepts.
+        minimum: u64,
+    },
+    /// The index's slot is retired, see `Arena::retired_count`.
+    Retired,
+    /// The arena could not grow to include the index's slot.
+    Reserve(TryReserveError),
+}
+
+impl fmt::Display for InsertAtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InsertAtError::StaleGeneration {
+                generation,
+                minimum,
+            } => write!(
+                f,
+                "generation {} is older than the oldest generation {} that the slot accepts",
+                generation, minimum
+            ),
+            InsertAtError::Retired => f.write_str("slot is retired"),
+            InsertAtError::Reserve(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InsertAtError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InsertAtError::Reserve(e) => Some(e),
             _ => None,
         }
     }
 }
 
-impl<T> IntoIterator for Arena<T> {
+impl<T, K, P: ReusePolicy> IntoIterator for Arena<T, K, P> {
     type Item = T;
     type IntoIter = IntoIter<T>;
     fn into_iter(self) -> Self::IntoIter {
@@ -1052,7 +4067,9 @@ impl<T> Iterator for IntoIter<T> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             match self.inner.next() {
-                Some(Entry::Free { .. }) => continue,
+                Some(Entry::Free { .. })
+                | Some(Entry::Reserved { .. })
+                | Some(Entry::Retired) => continue,
                 Some(Entry::Occupied { value, .. }) => {
                     self.len -= 1;
                     return Some(value);
@@ -1074,7 +4091,9 @@ impl<T> DoubleEndedIterator for IntoIter<T> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             match self.inner.next_back() {
-                Some(Entry::Free { .. }) => continue,
+                Some(Entry::Free { .. })
+                | Some(Entry::Reserved { .. })
+                | Some(Entry::Retired) => continue,
                 Some(Entry::Occupied { value, .. }) => {
                     self.len -= 1;
                     return Some(value);
@@ -1096,9 +4115,13 @@ impl<T> ExactSizeIterator for IntoIter<T> {
 
 impl<T> FusedIterator for IntoIter<T> {}
 
-impl<'a, T> IntoIterator for &'a Arena<T> {
-    type Item = (Index, &'a T);
-    type IntoIter = Iter<'a, T>;
+impl<'a, T, K, P> IntoIterator for &'a Arena<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
@@ -1125,18 +4148,24 @@ impl<'a, T> IntoIterator for &'a Arena<T> {
 /// }
 /// ```
 #[derive(Clone, Debug)]
-pub struct Iter<'a, T: 'a> {
+pub struct Iter<'a, T: 'a, K = Index> {
     len: usize,
     inner: iter::Enumerate<slice::Iter<'a, Entry<T>>>,
+    key: PhantomData<fn() -> K>,
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = (Index, &'a T);
+impl<'a, T, K> Iterator for Iter<'a, T, K>
+where
+    K: ArenaKey,
+{
+    type Item = (K, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             match self.inner.next() {
-                Some((_, &Entry::Free { .. })) => continue,
+                Some((_, &Entry::Free { .. }))
+                | Some((_, &Entry::Reserved { .. }))
+                | Some((_, &Entry::Retired)) => continue,
                 Some((
                     index,
                     &Entry::Occupied {
@@ -1145,8 +4174,8 @@ impl<'a, T> Iterator for Iter<'a, T> {
                     },
                 )) => {
                     self.len -= 1;
-                    let idx = Index { index, generation };
-                    return Some((idx, value));
+                    let idx = Index::new(index, generation);
+                    return Some((K::from_index(idx), value));
                 }
                 None => {
                     debug_assert_eq!(self.len, 0);
@@ -1161,11 +4190,16 @@ impl<'a, T> Iterator for Iter<'a, T> {
     }
 }
 
-impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+impl<'a, T, K> DoubleEndedIterator for Iter<'a, T, K>
+where
+    K: ArenaKey,
+{
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             match self.inner.next_back() {
-                Some((_, &Entry::Free { .. })) => continue,
+                Some((_, &Entry::Free { .. }))
+                | Some((_, &Entry::Reserved { .. }))
+                | Some((_, &Entry::Retired)) => continue,
                 Some((
                     index,
                     &Entry::Occupied {
@@ -1174,8 +4208,8 @@ impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
                     },
                 )) => {
                     self.len -= 1;
-                    let idx = Index { index, generation };
-                    return Some((idx, value));
+                    let idx = Index::new(index, generation);
+                    return Some((K::from_index(idx), value));
                 }
                 None => {
                     debug_assert_eq!(self.len, 0);
@@ -1186,17 +4220,24 @@ impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
     }
 }
 
-impl<'a, T> ExactSizeIterator for Iter<'a, T> {
+impl<'a, T, K> ExactSizeIterator for Iter<'a, T, K>
+where
+    K: ArenaKey,
+{
     fn len(&self) -> usize {
         self.len
     }
 }
 
-impl<'a, T> FusedIterator for Iter<'a, T> {}
+impl<'a, T, K> FusedIterator for Iter<'a, T, K> where K: ArenaKey {}
 
-impl<'a, T> IntoIterator for &'a mut Arena<T> {
-    type Item = (Index, &'a mut T);
-    type IntoIter = IterMut<'a, T>;
+impl<'a, T, K, P> IntoIterator for &'a mut Arena<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    type Item = (K, &'a mut T);
+    type IntoIter = IterMut<'a, T, K>;
     fn into_iter(self) -> Self::IntoIter {
         self.iter_mut()
     }
@@ -1223,18 +4264,24 @@ impl<'a, T> IntoIterator for &'a mut Arena<T> {
 /// }
 /// ```
 #[derive(Debug)]
-pub struct IterMut<'a, T: 'a> {
+pub struct IterMut<'a, T: 'a, K = Index> {
     len: usize,
     inner: iter::Enumerate<slice::IterMut<'a, Entry<T>>>,
+    key: PhantomData<fn() -> K>,
 }
 
-impl<'a, T> Iterator for IterMut<'a, T> {
-    type Item = (Index, &'a mut T);
+impl<'a, T, K> Iterator for IterMut<'a, T, K>
+where
+    K: ArenaKey,
+{
+    type Item = (K, &'a mut T);
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             match self.inner.next() {
-                Some((_, &mut Entry::Free { .. })) => continue,
+                Some((_, &mut Entry::Free { .. }))
+                | Some((_, &mut Entry::Reserved { .. }))
+                | Some((_, &mut Entry::Retired)) => continue,
                 Some((
                     index,
                     &mut Entry::Occupied {
@@ -1243,8 +4290,8 @@ impl<'a, T> Iterator for IterMut<'a, T> {
                     },
                 )) => {
                     self.len -= 1;
-                    let idx = Index { index, generation };
-                    return Some((idx, value));
+                    let idx = Index::new(index, generation);
+                    return Some((K::from_index(idx), value));
                 }
                 None => {
                     debug_assert_eq!(self.len, 0);
@@ -1259,11 +4306,16 @@ impl<'a, T> Iterator for IterMut<'a, T> {
     }
 }
 
-impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+impl<'a, T, K> DoubleEndedIterator for IterMut<'a, T, K>
+where
+    K: ArenaKey,
+{
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             match self.inner.next_back() {
-                Some((_, &mut Entry::Free { .. })) => continue,
+                Some((_, &mut Entry::Free { .. }))
+                | Some((_, &mut Entry::Reserved { .. }))
+                | Some((_, &mut Entry::Retired)) => continue,
                 Some((
                     index,
                     &mut Entry::Occupied {
@@ -1272,8 +4324,8 @@ impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
                     },
                 )) => {
                     self.len -= 1;
-                    let idx = Index { index, generation };
-                    return Some((idx, value));
+                    let idx = Index::new(index, generation);
+                    return Some((K::from_index(idx), value));
                 }
                 None => {
                     debug_assert_eq!(self.len, 0);
@@ -1284,13 +4336,16 @@ impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
     }
 }
 
-impl<'a, T> ExactSizeIterator for IterMut<'a, T> {
+impl<'a, T, K> ExactSizeIterator for IterMut<'a, T, K>
+where
+    K: ArenaKey,
+{
     fn len(&self) -> usize {
         self.len
     }
 }
 
-impl<'a, T> FusedIterator for IterMut<'a, T> {}
+impl<'a, T, K> FusedIterator for IterMut<'a, T, K> where K: ArenaKey {}
 
 /// An iterator that removes elements from the arena.
 ///
@@ -1318,29 +4373,38 @@ impl<'a, T> FusedIterator for IterMut<'a, T> {}
 /// assert!(arena.get(idx_2).is_none());
 /// ```
 #[derive(Debug)]
-pub struct Drain<'a, T: 'a> {
+pub struct Drain<'a, T: 'a, K = Index> {
     len: usize,
+    // The slots kept in the arena, because some of them are retired, have
+    // been emptied already; the others are drained from `offset` on.
+    kept: iter::Enumerate<vec::IntoIter<Entry<T>>>,
     inner: iter::Enumerate<vec::Drain<'a, Entry<T>>>,
+    offset: usize,
+    key: PhantomData<fn() -> K>,
 }
 
-impl<'a, T> Iterator for Drain<'a, T> {
-    type Item = (Index, T);
+impl<'a, T, K> Iterator for Drain<'a, T, K>
+where
+    K: ArenaKey,
+{
+    type Item = (K, T);
 
     fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
         loop {
-            match self.inner.next() {
-                Some((_, Entry::Free { .. })) => continue,
-                Some((index, Entry::Occupied { generation, value })) => {
-                    let idx = Index { index, generation };
-                    self.len -= 1;
-                    return Some((idx, value));
-                }
-                None => {
-                    debug_assert_eq!(self.len, 0);
-                    return None;
-                }
+            let slot = match self.kept.next() {
+                Some(slot) => slot,
+                None => match self.inner.next() {
+                    Some((index, entry)) => (index + offset, entry),
+                    None => break,
+                },
+            };
+            if let Some(item) = self.occupied(slot) {
+                return Some(item);
             }
         }
+        debug_assert_eq!(self.len, 0);
+        None
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -1348,34 +4412,60 @@ impl<'a, T> Iterator for Drain<'a, T> {
     }
 }
 
-impl<'a, T> DoubleEndedIterator for Drain<'a, T> {
+impl<'a, T, K> Drain<'a, T, K>
+where
+    K: ArenaKey,
+{
+    fn occupied(&mut self, (index, entry): (usize, Entry<T>)) -> Option<(K, T)> {
+        match entry {
+            Entry::Occupied { generation, value } => {
+                self.len -= 1;
+                Some((K::from_index(Index::new(index, generation)), value))
+            }
+            Entry::Free { .. } | Entry::Reserved { .. } | Entry::Retired => None,
+        }
+    }
+}
+
+impl<'a, T, K> DoubleEndedIterator for Drain<'a, T, K>
+where
+    K: ArenaKey,
+{
     fn next_back(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
         loop {
-            match self.inner.next_back() {
-                Some((_, Entry::Free { .. })) => continue,
-                Some((index, Entry::Occupied { generation, value })) => {
-                    let idx = Index { index, generation };
-                    self.len -= 1;
-                    return Some((idx, value));
-                }
-                None => {
-                    debug_assert_eq!(self.len, 0);
-                    return None;
-                }
+            let slot = match self.inner.next_back() {
+                Some((index, entry)) => (index + offset, entry),
+                None => match self.kept.next_back() {
+                    Some(slot) => slot,
+                    None => break,
+                },
+            };
+            if let Some(item) = self.occupied(slot) {
+                return Some(item);
             }
         }
+        debug_assert_eq!(self.len, 0);
+        None
     }
 }
 
-impl<'a, T> ExactSizeIterator for Drain<'a, T> {
+impl<'a, T, K> ExactSizeIterator for Drain<'a, T, K>
+where
+    K: ArenaKey,
+{
     fn len(&self) -> usize {
         self.len
     }
 }
 
-impl<'a, T> FusedIterator for Drain<'a, T> {}
+impl<'a, T, K> FusedIterator for Drain<'a, T, K> where K: ArenaKey {}
 
-impl<T> Extend<T> for Arena<T> {
+impl<T, K, P> Extend<T> for Arena<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
     fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         for t in iter {
             self.insert(t);
@@ -1395,16 +4485,30 @@ impl<T> FromIterator<T> for Arena<T> {
     }
 }
 
-impl<T> ops::Index<Index> for Arena<T> {
+impl<T, K, P> ops::Index<K> for Arena<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
     type Output = T;
 
-    fn index(&self, index: Index) -> &Self::Output {
-        self.get(index).expect("No element at index")
+    fn index(&self, index: K) -> &Self::Output {
+        match self.try_get(index) {
+            Ok(value) => value,
+            Err(e) => panic!("No element at index: {}", e),
+        }
     }
 }
 
-impl<T> ops::IndexMut<Index> for Arena<T> {
-    fn index_mut(&mut self, index: Index) -> &mut Self::Output {
-        self.get_mut(index).expect("No element at index")
+impl<T, K, P> ops::IndexMut<K> for Arena<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    fn index_mut(&mut self, index: K) -> &mut Self::Output {
+        match self.try_get_mut(index) {
+            Ok(value) => value,
+            Err(e) => panic!("No element at index: {}", e),
+        }
     }
 }
@@ -0,0 +1,326 @@
+//! Arenas that report their insertions and removals to an observer.
+//!
+//! See `ObservedArena` for details.
+
+use super::{Arena, ArenaKey, Drain, Index, Lifo, ReusePolicy};
+use core::ops;
+
+/// Callbacks invoked by an `ObservedArena` when elements are inserted into it
+/// or removed from it.
+///
+/// Every callback does nothing by default.
+///
+/// # Examples
+///
+/// ```
+/// use generational_arena::Index;
+/// use generational_arena::observed::{ArenaObserver, ObservedArena};
+/// use std::collections::HashMap;
+///
+/// #[derive(Default)]
+/// struct ByName(HashMap<String, Index>);
+///
+/// impl ArenaObserver<String> for ByName {
+///     fn on_insert(&mut self, i: Index, name: &String) {
+///         self.0.insert(name.clone(), i);
+///     }
+///
+///     fn on_remove(&mut self, _i: Index, name: &String) {
+///         self.0.remove(name);
+///     }
+///
+///     fn on_clear(&mut self) {
+///         self.0.clear();
+///     }
+/// }
+///
+/// let mut arena = ObservedArena::new(ByName::default());
+/// let idx = arena.insert("alice".to_string());
+/// assert_eq!(arena.observer().0["alice"], idx);
+///
+/// arena.remove(idx);
+/// assert!(arena.observer().0.is_empty());
+/// ```
+pub trait ArenaObserver<T> {
+    /// Called after `value` is inserted at index `i`.
+    fn on_insert(&mut self, i: Index, value: &T) {
+        let _ = (i, value);
+    }
+
+    /// Called when `value` is removed from index `i`, before it is returned
+    /// or dropped.
+    fn on_remove(&mut self, i: Index, value: &T) {
+        let _ = (i, value);
+    }
+
+    /// Called when every element is removed at once, by `clear` or `drain`,
+    /// instead of `on_remove` for each of them.
+    fn on_clear(&mut self) {}
+}
+
+/// An arena that calls an `ArenaObserver` whenever elements are inserted or
+/// removed, so that side indexes can be kept in sync with it.
+///
+/// `ObservedArena` dereferences to its `Arena`, for everything that only reads
+/// it. Elements changed in place, through `get_mut` or `iter_mut`, are not
+/// reported to the observer.
+#[derive(Debug)]
+pub struct ObservedArena<T, O, K = Index, P: ReusePolicy = Lifo> {
+    arena: Arena<T, K, P>,
+    observer: O,
+}
+
+impl<T, O> ObservedArena<T, O>
+where
+    O: ArenaObserver<T>,
+{
+    /// Constructs a new, empty arena, reporting to `observer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let arena = ObservedArena::<usize, ()>::new(());
+    /// assert!(arena.is_empty());
+    /// ```
+    pub fn new(observer: O) -> ObservedArena<T, O> {
+        ObservedArena::with_arena(Arena::new(), observer)
+    }
+}
+
+impl<T, O, K, P> ObservedArena<T, O, K, P>
+where
+    O: ArenaObserver<T>,
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    /// Wrap `arena`, reporting to `observer`. `on_insert` is called for every
+    /// element already in `arena`, in index order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::{Arena, Index};
+    /// use generational_arena::observed::{ArenaObserver, ObservedArena};
+    ///
+    /// struct Count(usize);
+    ///
+    /// impl<T> ArenaObserver<T> for Count {
+    ///     fn on_insert(&mut self, _i: Index, _value: &T) {
+    ///         self.0 += 1;
+    ///     }
+    /// }
+    ///
+    /// let arena: Arena<_> = (0..3).collect();
+    /// let arena = ObservedArena::with_arena(arena, Count(0));
+    /// assert_eq!(arena.observer().0, 3);
+    /// ```
+    pub fn with_arena(arena: Arena<T, K, P>, mut observer: O) -> Self {
+        for (i, value) in &arena {
+            observer.on_insert(i.into_index(), value);
+        }
+        ObservedArena { arena, observer }
+    }
+
+    /// Get the observer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let arena = ObservedArena::<usize, ()>::new(());
+    /// assert_eq!(arena.observer(), &());
+    /// ```
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Get the observer mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::<usize, ()>::new(());
+    /// *arena.observer_mut() = ();
+    /// ```
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Unwrap the arena and its observer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// let idx = arena.insert(42);
+    ///
+    /// let (arena, ()) = arena.into_parts();
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    pub fn into_parts(self) -> (Arena<T, K, P>, O) {
+        (self.arena, self.observer)
+    }
+
+    /// Insert `value` into the arena, then call `on_insert`. See
+    /// `Arena::insert`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// let idx = arena.insert(42);
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    pub fn insert(&mut self, value: T) -> K {
+        self.insert_with(|_| value)
+    }
+
+    /// Insert the value returned by `create` into the arena, then call
+    /// `on_insert`. See `Arena::insert_with`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// let idx = arena.insert_with(|idx| (42, idx));
+    /// assert_eq!(arena[idx], (42, idx));
+    /// ```
+    pub fn insert_with(&mut self, create: impl FnOnce(K) -> T) -> K {
+        let i = self.arena.insert_with(create);
+        self.observer.on_insert(i.into_index(), &self.arena[i]);
+        i
+    }
+
+    /// Remove the element at index `i`, calling `on_remove` if there is one.
+    /// See `Arena::remove`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// let idx = arena.insert(42);
+    ///
+    /// assert_eq!(arena.remove(idx), Some(42));
+    /// assert_eq!(arena.remove(idx), None);
+    /// ```
+    pub fn remove(&mut self, i: K) -> Option<T> {
+        let value = self.arena.remove(i)?;
+        self.observer.on_remove(i.into_index(), &value);
+        Some(value)
+    }
+
+    /// Retain only the elements for which `predicate` returns `true`, calling
+    /// `on_remove` for each of the others. See `Arena::retain`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// arena.insert(1);
+    /// arena.insert(2);
+    ///
+    /// arena.retain(|_, value| *value % 2 == 0);
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    pub fn retain(&mut self, mut predicate: impl FnMut(K, &mut T) -> bool) {
+        let observer = &mut self.observer;
+        self.arena.retain(|i, value| {
+            let retain = predicate(i, value);
+            if !retain {
+                observer.on_remove(i.into_index(), value);
+            }
+            retain
+        });
+    }
+
+    /// Remove every element, then call `on_clear`. See `Arena::clear`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// arena.insert(42);
+    ///
+    /// arena.clear();
+    /// assert!(arena.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.arena.clear();
+        self.observer.on_clear();
+    }
+
+    /// Call `on_clear`, then remove every element and return an iterator over
+    /// them. See `Arena::drain`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// let idx = arena.insert(42);
+    ///
+    /// assert_eq!(arena.drain().collect::<Vec<_>>(), vec![(idx, 42)]);
+    /// assert!(arena.is_empty());
+    /// ```
+    pub fn drain(&mut self) -> Drain<'_, T, K> {
+        self.observer.on_clear();
+        self.arena.drain()
+    }
+
+    /// Get a mutable reference to the element at index `i`. The change is not
+    /// reported to the observer. See `Arena::get_mut`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::observed::ObservedArena;
+    ///
+    /// let mut arena = ObservedArena::new(());
+    /// let idx = arena.insert(42);
+    ///
+    /// *arena.get_mut(idx).unwrap() += 1;
+    /// assert_eq!(arena[idx], 43);
+    /// ```
+    pub fn get_mut(&mut self, i: K) -> Option<&mut T> {
+        self.arena.get_mut(i)
+    }
+}
+
+impl<T, O, K, P> Default for ObservedArena<T, O, K, P>
+where
+    O: ArenaObserver<T> + Default,
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    fn default() -> Self {
+        ObservedArena::with_arena(Arena::default(), O::default())
+    }
+}
+
+impl<T, O, K, P: ReusePolicy> ops::Deref for ObservedArena<T, O, K, P> {
+    type Target = Arena<T, K, P>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.arena
+    }
+}
+
+impl<T> ArenaObserver<T> for () {}
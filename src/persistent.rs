@@ -0,0 +1,438 @@
+//! A persistent variant of `Arena`.
+//!
+//! See `PersistentArena` for details.
+
+use super::trie::{self, Trie, WIDTH};
+use super::{ArenaKey, Entry, Index};
+use core::cmp;
+use core::fmt;
+use core::iter::{self, FusedIterator};
+use core::marker::PhantomData;
+use core::mem;
+
+/// A persistent arena: inserting, removing or updating an element returns a
+/// new version of the arena, and leaves the old version untouched.
+///
+/// Versions share their storage, in a tree of chunks of a few slots: a new
+/// version only copies the chunk of the slot that changed and the few nodes
+/// above it, of which there are O(log n), and cloning a version is cheap.
+/// Indices and generations behave as in `Arena`, with free slots reused last
+/// in, first out, so an index handed out by one version refers to the same
+/// element in every version derived from it, until it is removed there.
+///
+/// # Examples
+///
+/// ```
+/// use generational_arena::persistent::PersistentArena;
+///
+/// let v0 = PersistentArena::new();
+/// let (v1, a) = v0.insert("a");
+/// let (v2, b) = v1.insert("b");
+/// let (v3, _) = v2.remove(a).unwrap();
+///
+/// assert!(v0.is_empty());
+/// assert_eq!(v1.get(a), Some(&"a"));
+/// assert_eq!((v2.get(a), v2.get(b)), (Some(&"a"), Some(&"b")));
+/// assert_eq!((v3.get(a), v3.get(b)), (None, Some(&"b")));
+/// ```
+pub struct PersistentArena<T, K = Index> {
+    slots: Trie<Entry<T>>,
+    free_list_head: Option<usize>,
+    len: usize,
+    key: PhantomData<fn() -> K>,
+}
+
+impl<T> PersistentArena<T> {
+    /// Constructs a new, empty persistent arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let arena = PersistentArena::<usize>::new();
+    /// assert!(arena.is_empty());
+    /// ```
+    pub fn new() -> PersistentArena<T> {
+        PersistentArena::with_key()
+    }
+}
+
+impl<T, K> PersistentArena<T, K>
+where
+    K: ArenaKey,
+{
+    /// Constructs a new, empty persistent arena that uses `K` for its
+    /// indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::CompactIndex;
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let arena = PersistentArena::<usize, CompactIndex>::with_key();
+    /// let (arena, idx) = arena.insert(42);
+    /// assert_eq!(idx.into_raw_parts(), (0, 0));
+    /// ```
+    pub fn with_key() -> PersistentArena<T, K> {
+        PersistentArena {
+            slots: Trie::new(),
+            free_list_head: None,
+            len: 0,
+            key: PhantomData,
+        }
+    }
+
+    /// Get a shared reference to the element at index `i` if it is in this
+    /// version of the arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let (arena, idx) = PersistentArena::new().insert(42);
+    /// assert_eq!(arena.get(idx), Some(&42));
+    ///
+    /// let (arena, _) = arena.remove(idx).unwrap();
+    /// assert_eq!(arena.get(idx), None);
+    /// ```
+    pub fn get(&self, i: K) -> Option<&T> {
+        let i = i.into_index();
+        match self.slots.get(i.index)? {
+            Entry::Occupied { generation, value } if *generation == i.generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Is the element at index `i` in this version of the arena?
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let (arena, idx) = PersistentArena::new().insert(42);
+    /// assert!(arena.contains(idx));
+    /// ```
+    pub fn contains(&self, i: K) -> bool {
+        self.get(i).is_some()
+    }
+
+    /// Get the number of elements in this version of the arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let (arena, _) = PersistentArena::new().insert(42);
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if this version of the arena contains no elements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let arena = PersistentArena::new();
+    /// let (next, _) = arena.insert(42);
+    /// assert!(arena.is_empty());
+    /// assert!(!next.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of slots in this version of the arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let arena = PersistentArena::<usize>::new();
+    /// assert_eq!(arena.capacity(), 0);
+    ///
+    /// let (arena, _) = arena.insert(42);
+    /// assert!(arena.capacity() > 0);
+    /// ```
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Iterate over shared references to the elements in this version of the
+    /// arena.
+    ///
+    /// Yields pairs of `(Index, &T)` items, in the order of their slots.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let (arena, a) = PersistentArena::new().insert(1);
+    /// let (arena, b) = arena.insert(2);
+    ///
+    /// let elements: Vec<_> = arena.iter().collect();
+    /// assert_eq!(elements, vec![(a, &1), (b, &2)]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        Iter {
+            len: self.len,
+            slots: self.slots.iter(),
+            slot: 0,
+            key: PhantomData,
+        }
+    }
+}
+
+impl<T, K> PersistentArena<T, K>
+where
+    T: Clone,
+    K: ArenaKey,
+{
+    /// Insert `value`, and return the new version of the arena along with the
+    /// value's index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena has no free slot left and cannot address any more
+    /// slots with its index type (see `ArenaKey::MAX_INDEX`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let arena = PersistentArena::new();
+    /// let (next, idx) = arena.insert(42);
+    /// assert_eq!(next.get(idx), Some(&42));
+    /// assert_eq!(arena.get(idx), None);
+    /// ```
+    pub fn insert(&self, value: T) -> (Self, K) {
+        let mut next = self.clone();
+        let i = next.insert_in_place(value);
+        (next, i)
+    }
+
+    /// Remove the element at index `i`, and return the new version of the
+    /// arena along with the removed element, or `None` if `i` is not in this
+    /// version of the arena.
+    ///
+    /// The element stays in the versions that share it, so the returned
+    /// element is a clone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let (arena, idx) = PersistentArena::new().insert(42);
+    /// let (next, value) = arena.remove(idx).unwrap();
+    /// assert_eq!(value, 42);
+    /// assert!(next.remove(idx).is_none());
+    /// assert_eq!(arena.get(idx), Some(&42));
+    /// ```
+    pub fn remove(&self, i: K) -> Option<(Self, T)> {
+        let i = i.into_index();
+        self.get(K::from_index(i))?;
+
+        let mut next = self.clone();
+        // See `Arena::remove`: a slot whose generation cannot be bumped any
+        // further is retired rather than freed.
+        let freed = if i.generation() < K::MAX_GENERATION {
+            next.free_list_head = Some(i.index);
+            Entry::Free {
+                next_free: self.free_list_head,
+                generation: i.generation() + 1,
+            }
+        } else {
+            Entry::Retired
+        };
+        next.len -= 1;
+        match mem::replace(next.slot_mut(i.index), freed) {
+            Entry::Occupied { value, .. } => Some((next, value)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Apply `f` to the element at index `i`, and return the new version of
+    /// the arena, or `None` if `i` is not in this version of the arena.
+    ///
+    /// The element keeps its index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::persistent::PersistentArena;
+    ///
+    /// let (arena, idx) = PersistentArena::new().insert(42);
+    /// let next = arena.update(idx, |value| *value += 1).unwrap();
+    /// assert_eq!(next.get(idx), Some(&43));
+    /// assert_eq!(arena.get(idx), Some(&42));
+    /// ```
+    pub fn update(&self, i: K, f: impl FnOnce(&mut T)) -> Option<Self> {
+        let i = i.into_index();
+        self.get(K::from_index(i))?;
+
+        let mut next = self.clone();
+        match next.slot_mut(i.index) {
+            Entry::Occupied { value, .. } => f(value),
+            _ => unreachable!(),
+        }
+        Some(next)
+    }
+
+    /// Inserts `value` into this version, copying the chunk of its slot and the
+    /// nodes above it first if another version shares them.
+    fn insert_in_place(&mut self, value: T) -> K {
+        if self.free_list_head.is_none() {
+            self.grow();
+        }
+        let i = self.free_list_head.expect("a slot is always free after growing");
+        let slot = self.slot_mut(i);
+        let (next_free, generation) = match *slot {
+            Entry::Free {
+                next_free,
+                generation,
+            } => (next_free, generation),
+            _ => panic!("corrupt free list"),
+        };
+        *slot = Entry::Occupied { generation, value };
+        self.free_list_head = next_free;
+        self.len += 1;
+        K::from_index(Index::new(i, generation))
+    }
+
+    /// Get an exclusive reference to slot `i`, copying its chunk and the
+    /// nodes above it first if another version shares them.
+    fn slot_mut(&mut self, i: usize) -> &mut Entry<T> {
+        self.slots.get_mut(i).expect("slot is in the arena")
+    }
+
+    /// Adds a chunk of free slots, reused in ascending order.
+    fn grow(&mut self) {
+        let start = self.slots.len();
+        let end = match K::MAX_INDEX.checked_sub(start) {
+            Some(left) => start + cmp::min(WIDTH - 1, left) + 1,
+            None => panic!("arena has no more indices available"),
+        };
+        for i in start..end {
+            self.slots.push(Entry::Free {
+                next_free: if i + 1 < end { Some(i + 1) } else { None },
+                generation: 0,
+            });
+        }
+        self.free_list_head = Some(start);
+    }
+}
+
+impl<T, K> Clone for PersistentArena<T, K> {
+    fn clone(&self) -> Self {
+        PersistentArena {
+            slots: self.slots.clone(),
+            free_list_head: self.free_list_head,
+            len: self.len,
+            key: PhantomData,
+        }
+    }
+}
+
+impl<T, K> Default for PersistentArena<T, K>
+where
+    K: ArenaKey,
+{
+    fn default() -> Self {
+        PersistentArena::with_key()
+    }
+}
+
+impl<T, K> fmt::Debug for PersistentArena<T, K>
+where
+    T: fmt::Debug,
+    K: ArenaKey + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over shared references to the elements of a version of a
+/// `PersistentArena`.
+///
+/// Yields pairs of `(Index, &T)` items, in the order of their slots.
+///
+/// This `struct` is created by the `iter` method on `PersistentArena`.
+#[derive(Clone, Debug)]
+pub struct Iter<'a, T: 'a, K = Index> {
+    len: usize,
+    slots: trie::Iter<'a, Entry<T>>,
+    slot: usize,
+    key: PhantomData<fn() -> K>,
+}
+
+impl<'a, T, K> Iterator for Iter<'a, T, K>
+where
+    K: ArenaKey,
+{
+    type Item = (K, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.len > 0 {
+            let i = self.slot;
+            self.slot += 1;
+            if let Entry::Occupied { generation, value } = self.slots.next()? {
+                self.len -= 1;
+                return Some((K::from_index(Index::new(i, *generation)), value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T, K> ExactSizeIterator for Iter<'a, T, K>
+where
+    K: ArenaKey,
+{
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, T, K> FusedIterator for Iter<'a, T, K> where K: ArenaKey {}
+
+impl<'a, T, K> IntoIterator for &'a PersistentArena<T, K>
+where
+    K: ArenaKey,
+{
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, T, K>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, K> iter::FromIterator<T> for PersistentArena<T, K>
+where
+    T: Clone,
+    K: ArenaKey,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut arena = PersistentArena::with_key();
+        for value in iter {
+            arena.insert_in_place(value);
+        }
+        arena
+    }
+}
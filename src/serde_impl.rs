@@ -1,9 +1,13 @@
-use super::{Arena, Entry, Index, Vec, DEFAULT_CAPACITY};
+use super::{
+    Arena, ArenaDelta, ArenaKey, CompactIndex, Entry, FreeSlots, Index, ReuseKind, ReusePolicy,
+    TypedIndex, Vec, VecDeque, DEFAULT_CAPACITY,
+};
 use core::cmp;
 use core::fmt;
 use core::iter;
 use core::marker::PhantomData;
-use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use core::sync::atomic::AtomicUsize;
+use serde::de::{Deserialize, Deserializer, Error, SeqAccess, Visitor};
 use serde::ser::{Serialize, Serializer};
 
 impl Serialize for Index {
@@ -13,7 +17,7 @@ impl Serialize for Index {
     {
         // Note: do not change the serialization format, or it may break
         // forward and backward compatibility of serialized data!
-        (self.index, self.generation).serialize(serializer)
+        self.into_raw_parts().serialize(serializer)
     }
 }
 
@@ -22,14 +26,61 @@ impl<'de> Deserialize<'de> for Index {
     where
         D: Deserializer<'de>,
     {
-        let (index, generation) = Deserialize::deserialize(deserializer)?;
-        Ok(Index { index, generation })
+        let (index, generation): (usize, u64) = Deserialize::deserialize(deserializer)?;
+        if generation == u64::MAX {
+            return Err(D::Error::custom("`u64::MAX` is not a valid generation"));
+        }
+        Ok(Index::from_raw_parts(index, generation))
+    }
+}
+
+impl<T> Serialize for TypedIndex<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Typed indices share the untyped `Index` serialization format.
+        self.index.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for TypedIndex<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Index::deserialize(deserializer).map(TypedIndex::from)
     }
 }
 
-impl<T> Serialize for Arena<T>
+impl Serialize for CompactIndex {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Note: do not change the serialization format, or it may break
+        // forward and backward compatibility of serialized data!
+        self.into_raw_parts().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CompactIndex {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (index, generation): (u32, u32) = Deserialize::deserialize(deserializer)?;
+        if generation == u32::MAX {
+            return Err(D::Error::custom("`u32::MAX` is not a valid generation"));
+        }
+        Ok(CompactIndex::from_raw_parts(index, generation))
+    }
+}
+
+impl<T, K, P> Serialize for Arena<T, K, P>
 where
     T: Serialize,
+    P: ReusePolicy,
 {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -39,14 +90,16 @@ where
         // forward and backward compatibility of serialized data!
         serializer.collect_seq(self.items.iter().map(|entry| match entry {
             Entry::Occupied { generation, value } => Some((generation, value)),
-            Entry::Free { .. } => None,
+            Entry::Free { .. } | Entry::Reserved { .. } | Entry::Retired => None,
         }))
     }
 }
 
-impl<'de, T> Deserialize<'de> for Arena<T>
+impl<'de, T, K, P> Deserialize<'de> for Arena<T, K, P>
 where
     T: Deserialize<'de>,
+    K: ArenaKey,
+    P: ReusePolicy,
 {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -56,11 +109,11 @@ where
     }
 }
 
-struct ArenaVisitor<T> {
-    marker: PhantomData<fn() -> Arena<T>>,
+struct ArenaVisitor<T, K, P: ReusePolicy> {
+    marker: PhantomData<Arena<T, K, P>>,
 }
 
-impl<T> ArenaVisitor<T> {
+impl<T, K, P: ReusePolicy> ArenaVisitor<T, K, P> {
     fn new() -> Self {
         Self {
             marker: PhantomData,
@@ -68,11 +121,13 @@ impl<T> ArenaVisitor<T> {
     }
 }
 
-impl<'de, T> Visitor<'de> for ArenaVisitor<T>
+impl<'de, T, K, P> Visitor<'de> for ArenaVisitor<T, K, P>
 where
     T: Deserialize<'de>,
+    K: ArenaKey,
+    P: ReusePolicy,
 {
-    type Value = Arena<T>;
+    type Value = Arena<T, K, P>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         write!(formatter, "a generational arena")
@@ -85,17 +140,29 @@ where
         let init_cap = access.size_hint().unwrap_or(DEFAULT_CAPACITY);
         let mut items = Vec::with_capacity(init_cap);
 
+        // The generations of free slots are not serialized, so every free slot
+        // gets a generation that is newer than that of any occupied slot, as
+        // far as the arena's index type allows.
         let mut generation = 0;
         while let Some(element) = access.next_element::<Option<(u64, T)>>()? {
+            if items.len() > K::MAX_INDEX {
+                return Err(M::Error::custom("too many slots for the arena's index type"));
+            }
             let item = match element {
                 Some((gen, value)) => {
-                    generation = cmp::max(generation, gen);
+                    if gen > K::MAX_GENERATION {
+                        return Err(M::Error::custom("generation out of range for the arena's index type"));
+                    }
+                    generation = cmp::max(generation, cmp::min(gen + 1, K::MAX_GENERATION));
                     Entry::Occupied {
                         generation: gen,
                         value,
                     }
                 }
-                None => Entry::Free { next_free: None },
+                None => Entry::Free {
+                    next_free: None,
+                    generation: 0,
+                },
             };
             items.push(item);
         }
@@ -104,27 +171,307 @@ where
         if items.len() < items.capacity() {
             let add_cap = items.capacity() - items.len();
             items.reserve_exact(add_cap);
-            items.extend(iter::repeat_with(|| Entry::Free { next_free: None }).take(add_cap));
+            items.extend(
+                iter::repeat_with(|| Entry::Free {
+                    next_free: None,
+                    generation: 0,
+                })
+                .take(add_cap),
+            );
             debug_assert_eq!(items.len(), items.capacity());
         }
 
-        let mut free_list_head = None;
         let mut len = items.len();
-        // Iterates `arena.items` in reverse order so that free_list concatenates
-        // indices in ascending order.
-        for (idx, entry) in items.iter_mut().enumerate().rev() {
-            if let Entry::Free { next_free } = entry {
-                *next_free = free_list_head;
-                free_list_head = Some(idx);
+        for entry in items.iter_mut() {
+            if let Entry::Free {
+                generation: free_generation,
+                ..
+            } = entry
+            {
                 len -= 1;
+                *free_generation = generation;
             }
         }
 
-        Ok(Arena {
+        let mut arena = Arena {
             items,
             generation,
+            free_list_head: None,
+            free_slots: Default::default(),
+            quarantine: VecDeque::new(),
+            quarantine_len: 0,
+            reserved: AtomicUsize::new(0),
+            journal: None,
+            tracker: None,
+            changes: None,
+            len,
+            retired: 0,
+            key: PhantomData,
+            policy: PhantomData,
+        };
+        arena.rebuild_free_list();
+        Ok(arena)
+    }
+}
+
+// The kinds of slots in a serialized `ArenaDelta`.
+const FREE: u8 = 0;
+const OCCUPIED: u8 = 1;
+const RESERVED: u8 = 2;
+const RETIRED: u8 = 3;
+
+// A changed slot: its index, kind, generation, next free slot and element.
+type DeltaSlot<T> = (usize, u8, u64, Option<usize>, Option<T>);
+
+impl<T, K, P> Serialize for ArenaDelta<T, K, P>
+where
+    T: Serialize,
+    P: ReusePolicy,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Note: do not change the serialization format, or it may break
+        // forward and backward compatibility of serialized data!
+        let slots = DeltaSlots(&self.slots);
+        let heap = self.free_slots.heap().into_iter().flatten();
+        let mut free_heap: Vec<_> = heap.map(|&cmp::Reverse(i)| i).collect();
+        free_heap.sort_unstable();
+        (
+            self.base_capacity,
+            self.base_len,
+            self.capacity,
+            slots,
+            self.generation,
+            self.free_list_head,
+            self.free_slots.tail(),
+            &free_heap[..],
+            &self.quarantine[..],
+            self.quarantine_len,
+            self.len,
+            self.retired,
+        )
+            .serialize(serializer)
+    }
+}
+
+struct DeltaSlots<'a, T>(&'a [(usize, Entry<T>)]);
+
+impl<'a, T> Serialize for DeltaSlots<'a, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.iter().map(|(i, entry)| -> DeltaSlot<&T> {
+            match entry {
+                Entry::Free {
+                    next_free,
+                    generation,
+                } => (*i, FREE, *generation, *next_free, None),
+                Entry::Occupied { generation, value } => {
+                    (*i, OCCUPIED, *generation, None, Some(value))
+                }
+                Entry::Reserved { generation } => (*i, RESERVED, *generation, None, None),
+                Entry::Retired => (*i, RETIRED, 0, None, None),
+            }
+        }))
+    }
+}
+
+impl<'de, T, K, P> Deserialize<'de> for ArenaDelta<T, K, P>
+where
+    T: Deserialize<'de>,
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(12, ArenaDeltaVisitor {
+            marker: PhantomData,
+        })
+    }
+}
+
+struct ArenaDeltaVisitor<T, K, P: ReusePolicy> {
+    marker: PhantomData<ArenaDelta<T, K, P>>,
+}
+
+impl<'de, T, K, P> Visitor<'de> for ArenaDeltaVisitor<T, K, P>
+where
+    T: Deserialize<'de>,
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    type Value = ArenaDelta<T, K, P>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a generational arena delta")
+    }
+
+    fn visit_seq<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+    where
+        M: SeqAccess<'de>,
+    {
+        fn next<'de, M, X>(access: &mut M, position: usize) -> Result<X, M::Error>
+        where
+            M: SeqAccess<'de>,
+            X: Deserialize<'de>,
+        {
+            access
+                .next_element()?
+                .ok_or_else(|| M::Error::invalid_length(position, &"a tuple of 12 elements"))
+        }
+
+        let base_capacity: usize = next(&mut access, 0)?;
+        let base_len = next(&mut access, 1)?;
+        let capacity: usize = next(&mut access, 2)?;
+        let SeqOf(slots): SeqOf<DeltaSlot<T>> = next(&mut access, 3)?;
+        let generation: u64 = next(&mut access, 4)?;
+        let free_list_head: Option<usize> = next(&mut access, 5)?;
+        let free_list_tail: Option<usize> = next(&mut access, 6)?;
+        let SeqOf(free_heap): SeqOf<usize> = next(&mut access, 7)?;
+        let SeqOf(quarantine): SeqOf<usize> = next(&mut access, 8)?;
+        let quarantine_len = next(&mut access, 9)?;
+        let len = next(&mut access, 10)?;
+        let retired = next(&mut access, 11)?;
+
+        // Check that the delta fits the arena's index type, and that applying
+        // it never indexes out of bounds.
+        if capacity > 0 && capacity - 1 > K::MAX_INDEX {
+            return Err(M::Error::custom(
+                "too many slots for the arena's index type",
+            ));
+        }
+        let out_of_generations =
+            || M::Error::custom("generation out of range for the arena's index type");
+        if generation > K::MAX_GENERATION {
+            return Err(out_of_generations());
+        }
+        let out_of_bounds = || M::Error::custom("slot index out of bounds");
+        let mut next_slot = 0;
+        let mut new_slots = 0;
+        let mut entries = Vec::with_capacity(slots.len());
+        for (i, kind, generation, next_free, value) in slots {
+            if i < next_slot {
+                return Err(M::Error::custom("slots out of order"));
+            }
+            if i >= capacity || matches!(next_free, Some(j) if j >= capacity) {
+                return Err(out_of_bounds());
+            }
+            if generation > K::MAX_GENERATION {
+                return Err(out_of_generations());
+            }
+            next_slot = i + 1;
+            if i >= base_capacity {
+                new_slots += 1;
+            }
+            let entry = match (kind, value) {
+                (FREE, None) => Entry::Free {
+                    next_free,
+                    generation,
+                },
+                (OCCUPIED, Some(value)) => Entry::Occupied { generation, value },
+                (RESERVED, None) => Entry::Reserved { generation },
+                (RETIRED, None) => Entry::Retired,
+                _ => return Err(M::Error::custom("invalid slot")),
+            };
+            entries.push((i, entry));
+        }
+        let free_slots = free_list_head
+            .iter()
+            .chain(&free_list_tail)
+            .chain(&free_heap)
+            .chain(&quarantine);
+        for &i in free_slots {
+            if i >= capacity {
+                return Err(out_of_bounds());
+            }
+        }
+        // The slots past the end of the old arena are all new, so they must
+        // all be in the delta.
+        if capacity > base_capacity && new_slots != capacity - base_capacity {
+            return Err(M::Error::custom("missing slots"));
+        }
+        // Only `Fifo` arenas keep the tail of their free list, and
+        // `LowestFirst` ones keep a heap of free slots instead of a list.
+        let matches_policy = match P::KIND {
+            ReuseKind::Lifo => free_list_tail.is_none() && free_heap.is_empty(),
+            ReuseKind::Fifo => {
+                free_list_head.is_some() == free_list_tail.is_some() && free_heap.is_empty()
+            }
+            ReuseKind::LowestFirst => free_list_head.is_none() && free_list_tail.is_none(),
+        };
+        if !matches_policy {
+            return Err(M::Error::custom("free slots of another reuse policy"));
+        }
+        let mut free_slots = P::FreeSlots::default();
+        free_slots.set_tail(free_list_tail);
+        if let Some(heap) = free_slots.heap_mut() {
+            *heap = free_heap.into_iter().map(cmp::Reverse).collect();
+        }
+
+        Ok(ArenaDelta {
+            base_capacity,
+            base_len,
+            capacity,
+            slots: entries,
+            generation,
             free_list_head,
+            free_slots,
+            quarantine,
+            quarantine_len,
             len,
+            retired,
+            key: PhantomData,
+        })
+    }
+}
+
+/// A sequence, deserialized without relying on serde's `alloc` feature.
+struct SeqOf<X>(Vec<X>);
+
+impl<'de, X> Deserialize<'de> for SeqOf<X>
+where
+    X: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(SeqOfVisitor {
+            marker: PhantomData,
         })
     }
 }
+
+struct SeqOfVisitor<X> {
+    marker: PhantomData<X>,
+}
+
+impl<'de, X> Visitor<'de> for SeqOfVisitor<X>
+where
+    X: Deserialize<'de>,
+{
+    type Value = SeqOf<X>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a sequence")
+    }
+
+    fn visit_seq<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+    where
+        M: SeqAccess<'de>,
+    {
+        let mut items = Vec::with_capacity(cmp::min(access.size_hint().unwrap_or(0), 4096));
+        while let Some(item) = access.next_element()? {
+            items.push(item);
+        }
+        Ok(SeqOf(items))
+    }
+}
@@ -0,0 +1,222 @@
+//! Snapshots of `Arena`s.
+//!
+//! See `Arena::snapshot` and `Arena::restore` for details.
+
+use super::trie::Trie;
+use super::{ArenaKey, Entry, Index, Lifo, ReusePolicy, Vec, VecDeque};
+use core::cmp;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+
+/// An immutable copy of an arena, which the arena can be restored to.
+///
+/// This `struct` is created by `Arena::snapshot`. Snapshots share the slots
+/// that they have in common with each other and with their arena, in chunks
+/// of a few slots, so holding many of them, for example as an undo stack,
+/// costs memory in proportion to the slots that changed between them.
+pub struct ArenaSnapshot<T, K = Index, P: ReusePolicy = Lifo> {
+    pub(crate) items: Trie<Entry<T>>,
+    pub(crate) generation: u64,
+    pub(crate) free_list_head: Option<usize>,
+    pub(crate) free_slots: P::FreeSlots,
+    pub(crate) quarantine: VecDeque<usize>,
+    pub(crate) quarantine_len: usize,
+    pub(crate) len: usize,
+    pub(crate) retired: usize,
+    pub(crate) key: PhantomData<fn() -> K>,
+}
+
+/// The slots of an arena as of the last snapshot taken or restored, shared
+/// with that snapshot, and the slots that changed since.
+#[derive(Debug)]
+pub(crate) struct Tracker<T> {
+    base: Trie<Entry<T>>,
+    // The slots that changed, as a bitset, and the set ones in the order they
+    // were set.
+    bits: Vec<u64>,
+    slots: Vec<usize>,
+    // The slots from this one onwards may have been dropped and created
+    // again, so they are not tracked one by one.
+    min_len: usize,
+}
+
+impl<T, K, P> ArenaSnapshot<T, K, P>
+where
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    /// Get a shared reference to the element at index `i` as it was when this
+    /// snapshot was taken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    ///
+    /// let snapshot = arena.snapshot();
+    /// arena[idx] = 2;
+    /// assert_eq!(snapshot.get(idx), Some(&1));
+    /// ```
+    pub fn get(&self, i: K) -> Option<&T> {
+        let i = i.into_index();
+        match self.items.get(i.index)? {
+            Entry::Occupied { generation, value } if *generation == i.generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the number of elements in the arena when this snapshot was taken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.insert(1);
+    ///
+    /// let snapshot = arena.snapshot();
+    /// arena.insert(2);
+    /// assert_eq!(snapshot.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the arena contained no elements when this snapshot was
+    /// taken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::<usize>::new();
+    /// assert!(arena.snapshot().is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T, K, P: ReusePolicy> Clone for ArenaSnapshot<T, K, P> {
+    fn clone(&self) -> Self {
+        ArenaSnapshot {
+            items: self.items.clone(),
+            generation: self.generation,
+            free_list_head: self.free_list_head,
+            free_slots: self.free_slots.clone(),
+            quarantine: self.quarantine.clone(),
+            quarantine_len: self.quarantine_len,
+            len: self.len,
+            retired: self.retired,
+            key: PhantomData,
+        }
+    }
+}
+
+impl<T, K, P> fmt::Debug for ArenaSnapshot<T, K, P>
+where
+    T: fmt::Debug,
+    P: ReusePolicy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArenaSnapshot")
+            .field("items", &self.items)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<T> Tracker<T> {
+    pub(crate) fn new() -> Self {
+        Tracker {
+            base: Trie::new(),
+            bits: Vec::new(),
+            slots: Vec::new(),
+            min_len: 0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn mark(&mut self, i: usize) {
+        if i >= self.min_len {
+            return;
+        }
+        let (word, bit) = (i / 64, 1 << (i % 64));
+        if self.bits[word] & bit == 0 {
+            self.bits[word] |= bit;
+            self.slots.push(i);
+        }
+    }
+
+    /// Records that the slots from `len` onwards have been dropped.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.min_len = cmp::min(self.min_len, len);
+    }
+
+    /// Returns the marked slots, and unmarks them.
+    fn take_slots(&mut self) -> Vec<usize> {
+        for &i in &self.slots {
+            self.bits[i / 64] &= !(1 << (i % 64));
+        }
+        mem::take(&mut self.slots)
+    }
+
+    /// Starts tracking the changes since `base`, which holds the arena's
+    /// slots.
+    // `usize::div_ceil` needs Rust 1.73.
+    #[allow(clippy::manual_div_ceil)]
+    fn reset(&mut self, base: Trie<Entry<T>>) {
+        self.take_slots();
+        self.min_len = base.len();
+        self.bits.resize((base.len() + 63) / 64, 0);
+        self.base = base;
+    }
+}
+
+impl<T: Clone> Tracker<T> {
+    /// Copies the slots of `items` that changed into the base, and returns
+    /// it.
+    pub(crate) fn sync(&mut self, items: &[Entry<T>]) -> Trie<Entry<T>> {
+        let start = cmp::min(self.min_len, items.len());
+        let mut base = mem::replace(&mut self.base, Trie::new());
+        for i in self.take_slots() {
+            match base.get_mut(i) {
+                Some(slot) if i < start => *slot = items[i].clone(),
+                _ => {}
+            }
+        }
+        base.truncate(start);
+        for entry in &items[start..] {
+            base.push(entry.clone());
+        }
+        self.reset(base.clone());
+        base
+    }
+
+    /// Returns the slots of the arena, which has `items_len` slots, that may
+    /// differ from those of `target`, in ascending order, up to the slot that
+    /// is returned along with them, from which on every slot may differ.
+    ///
+    /// Then starts tracking the changes since `target`, which the caller
+    /// makes `items` equal to.
+    pub(crate) fn changed_slots(
+        &mut self,
+        items_len: usize,
+        target: &Trie<Entry<T>>,
+    ) -> (Vec<usize>, usize) {
+        let start = cmp::min(cmp::min(self.min_len, items_len), target.len());
+        let mut slots = self.take_slots();
+        slots.retain(|&i| i < start);
+        self.base.diff(target, start, |range| slots.extend(range));
+        slots.sort_unstable();
+        slots.dedup();
+        self.reset(target.clone());
+        (slots, start)
+    }
+}
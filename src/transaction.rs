@@ -0,0 +1,479 @@
+//! Transactions for `Arena`.
+//!
+//! See `Arena::begin` and `Arena::transaction` for details.
+
+use super::changes::{Change, Changes};
+use super::{Arena, ArenaKey, BTreeSet, Box, Entry, FreeSlots, Index, Lifo, ReusePolicy, Vec};
+use core::cmp;
+use core::fmt;
+
+/// A batch of changes to an arena that is either committed or rolled back
+/// as a whole.
+///
+/// This `struct` is created by `Arena::begin`. While it is alive, the arena
+/// records the previous state of every slot that the transaction changes, so
+/// that rolling back takes time proportional to the number of changes rather
+/// than to the size of the arena. Dropping the transaction without committing
+/// it rolls it back, while forgetting it, with `mem::forget`, keeps its
+/// changes like committing it.
+pub struct Transaction<'a, T: 'a, K: 'a = Index, P: 'a + ReusePolicy = Lifo> {
+    arena: &'a mut Arena<T, K, P>,
+    // Lent to the arena while the arena changes, see `with_journal`.
+    journal: Option<Box<Journal<T>>>,
+}
+
+/// The changes made by a transaction, in order, along with the state of the
+/// arena when the transaction began.
+#[derive(Debug)]
+pub(crate) struct Journal<T> {
+    items_len: usize,
+    len: usize,
+    retired: usize,
+    generation: u64,
+    free_list_head: Option<usize>,
+    free_list_tail: Option<usize>,
+    // The slots whose previous state has been saved already.
+    touched: BTreeSet<usize>,
+    undo: Vec<Undo<T>>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Undo<T> {
+    // The slot held this entry before the transaction changed it.
+    Slot(usize, Entry<T>),
+    HeapPush(usize),
+    HeapPop(usize),
+    QuarantinePush,
+    QuarantinePop(usize),
+    // The element at this index was in this set of the arena's changes.
+    Change(Index, Option<Change>),
+}
+
+impl<T> Journal<T> {
+    pub(crate) fn new<K, P: ReusePolicy>(arena: &Arena<T, K, P>) -> Self {
+        Journal {
+            items_len: arena.items.len(),
+            len: arena.len,
+            retired: arena.retired,
+            generation: arena.generation,
+            free_list_head: arena.free_list_head,
+            free_list_tail: arena.free_slots.tail(),
+            touched: BTreeSet::new(),
+            undo: Vec::new(),
+        }
+    }
+
+    /// Saves the state of the vacant slot `i` before it changes, unless it
+    /// has been saved already.
+    pub(crate) fn touch(&mut self, i: usize, entry: &Entry<T>) {
+        if !self.needs_saving(i) {
+            return;
+        }
+        let entry = match *entry {
+            Entry::Free {
+                next_free,
+                generation,
+            } => Entry::Free {
+                next_free,
+                generation,
+            },
+            Entry::Reserved { generation } => Entry::Reserved { generation },
+            Entry::Retired => Entry::Retired,
+            // The transaction saves occupied slots itself, before changing
+            // them, because only it can clone their value.
+            Entry::Occupied { .. } => unreachable!("occupied slot changed without being saved"),
+        };
+        self.undo.push(Undo::Slot(i, entry));
+    }
+
+    pub(crate) fn log(&mut self, undo: Undo<T>) {
+        self.undo.push(undo);
+    }
+
+    /// Slots created during the transaction are dropped on rollback, so their
+    /// state is never saved.
+    fn needs_saving(&mut self, i: usize) -> bool {
+        i < self.items_len && self.touched.insert(i)
+    }
+
+    fn rollback<K, P: ReusePolicy>(self, arena: &mut Arena<T, K, P>) {
+        let mut heap_pushed = Vec::new();
+        let mut heap_popped = Vec::new();
+        for undo in self.undo.into_iter().rev() {
+            match undo {
+                Undo::Slot(i, entry) => arena.items[i] = entry,
+                Undo::HeapPush(i) => heap_pushed.push(i),
+                Undo::HeapPop(i) => heap_popped.push(i),
+                Undo::QuarantinePush => {
+                    arena.quarantine.pop_back();
+                }
+                Undo::QuarantinePop(i) => arena.quarantine.push_front(i),
+                Undo::Change(i, change) => {
+                    if let Some(changes) = &mut arena.changes {
+                        changes.set(i, change);
+                    }
+                }
+            }
+        }
+        arena.items.truncate(self.items_len);
+        arena.len = self.len;
+        arena.retired = self.retired;
+        arena.generation = self.generation;
+        arena.free_list_head = self.free_list_head;
+        arena.free_slots.set_tail(self.free_list_tail);
+
+        // The order of the heap does not matter, so only the slots that were
+        // popped and not pushed back, or the other way around, are restored.
+        heap_pushed.sort_unstable();
+        heap_popped.sort_unstable();
+        let mut removed = Vec::new();
+        let mut restored = Vec::new();
+        let (mut pushed, mut popped) = (heap_pushed.into_iter(), heap_popped.into_iter());
+        let (mut next_pushed, mut next_popped) = (pushed.next(), popped.next());
+        loop {
+            match (next_pushed, next_popped) {
+                (Some(i), Some(j)) => match i.cmp(&j) {
+                    cmp::Ordering::Less => {
+                        removed.push(i);
+                        next_pushed = pushed.next();
+                    }
+                    cmp::Ordering::Greater => {
+                        restored.push(j);
+                        next_popped = popped.next();
+                    }
+                    cmp::Ordering::Equal => {
+                        next_pushed = pushed.next();
+                        next_popped = popped.next();
+                    }
+                },
+                (Some(i), None) => {
+                    removed.push(i);
+                    next_pushed = pushed.next();
+                }
+                (None, Some(j)) => {
+                    restored.push(j);
+                    next_popped = popped.next();
+                }
+                (None, None) => break,
+            }
+        }
+        if !removed.is_empty() {
+            arena
+                .free_slots
+                .retain_heap(|i| removed.binary_search(&i).is_err());
+        }
+        if let Some(heap) = arena.free_slots.heap_mut() {
+            heap.extend(restored.into_iter().map(cmp::Reverse));
+        }
+    }
+}
+
+impl<'a, T, K, P> Transaction<'a, T, K, P>
+where
+    T: Clone,
+    K: ArenaKey,
+    P: ReusePolicy,
+{
+    pub(crate) fn new(arena: &'a mut Arena<T, K, P>) -> Self {
+        arena.flush_reserved();
+        let journal = Some(Box::new(Journal::new(arena)));
+        Transaction { arena, journal }
+    }
+
+    /// Runs `f` with the journal lent to the arena, so that the arena records
+    /// its changes in it.
+    ///
+    /// The arena only holds the journal for the duration of the call: if the
+    /// transaction is forgotten rather than dropped, the arena is left as if
+    /// it had been committed.
+    fn with_journal<R>(&mut self, f: impl FnOnce(&mut Arena<T, K, P>) -> R) -> R {
+        self.arena.journal = self.journal.take();
+        let result = f(self.arena);
+        self.journal = self.arena.journal.take();
+        result
+    }
+
+    /// Saves the element at index `i` before it changes, and returns whether
+    /// there is one.
+    fn save(&mut self, i: K) -> bool {
+        let i = i.into_index();
+        let journal = self
+            .journal
+            .as_mut()
+            .expect("transactions always have a journal");
+        match self.arena.items.get(i.index) {
+            Some(Entry::Occupied { generation, value }) if *generation == i.generation() => {
+                if journal.needs_saving(i.index) {
+                    let entry = Entry::Occupied {
+                        generation: *generation,
+                        value: value.clone(),
+                    };
+                    journal.log(Undo::Slot(i.index, entry));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Insert `value` into the arena, allocating more capacity if necessary,
+    /// and return its index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// let mut tx = arena.begin();
+    /// let idx = tx.insert(42);
+    /// assert_eq!(tx.get(idx), Some(&42));
+    /// tx.rollback();
+    ///
+    /// assert!(arena.get(idx).is_none());
+    /// ```
+    pub fn insert(&mut self, value: T) -> K {
+        self.with_journal(|arena| arena.insert(value))
+    }
+
+    /// Insert the value returned by `create` into the arena, allocating more
+    /// capacity if necessary, and return its index.
+    ///
+    /// `create` is called with the new value's associated index, allowing
+    /// values that know their own index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    ///
+    /// let mut tx = arena.begin();
+    /// let idx = tx.insert_with(|idx| (42, idx));
+    /// tx.commit();
+    ///
+    /// assert_eq!(arena[idx], (42, idx));
+    /// ```
+    pub fn insert_with(&mut self, create: impl FnOnce(K) -> T) -> K {
+        self.with_journal(|arena| arena.insert_with(create))
+    }
+
+    /// Remove the element at index `i` from the arena, and return it.
+    ///
+    /// The arena keeps a clone of the element until the transaction ends, so
+    /// that it can be restored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let mut tx = arena.begin();
+    /// assert_eq!(tx.remove(idx), Some(42));
+    /// assert_eq!(tx.remove(idx), None);
+    /// tx.rollback();
+    ///
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    pub fn remove(&mut self, i: K) -> Option<T> {
+        if !self.save(i) {
+            return None;
+        }
+        self.with_journal(|arena| arena.remove(i))
+    }
+
+    /// Get a shared reference to the element at index `i` if it is in the
+    /// arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let tx = arena.begin();
+    /// assert_eq!(tx.get(idx), Some(&42));
+    /// ```
+    pub fn get(&self, i: K) -> Option<&T> {
+        self.arena.get(i)
+    }
+
+    /// Get an exclusive reference to the element at index `i` if it is in
+    /// the arena.
+    ///
+    /// The first time an element that was in the arena before the
+    /// transaction began is borrowed, it is cloned, so that it can be
+    /// restored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let mut tx = arena.begin();
+    /// *tx.get_mut(idx).unwrap() += 1;
+    /// tx.rollback();
+    ///
+    /// assert_eq!(arena[idx], 42);
+    /// ```
+    pub fn get_mut(&mut self, i: K) -> Option<&mut T> {
+        if !self.save(i) {
+            return None;
+        }
+        // The element is handed out after the journal is taken back, so the
+        // change is recorded up front.
+        let i = i.into_index();
+        self.with_journal(|arena| {
+            arena.touch(i.index);
+            arena.record(i, Changes::modify);
+        });
+        match &mut self.arena.items[i.index] {
+            Entry::Occupied { value, .. } => Some(value),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Is the element at index `i` in the arena?
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(42);
+    ///
+    /// let mut tx = arena.begin();
+    /// tx.remove(idx);
+    /// assert!(!tx.contains(idx));
+    /// ```
+    pub fn contains(&self, i: K) -> bool {
+        self.arena.contains(i)
+    }
+
+    /// Get the number of elements in the arena, including the changes made
+    /// by this transaction so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// arena.insert(1);
+    ///
+    /// let mut tx = arena.begin();
+    /// tx.insert(2);
+    /// assert_eq!(tx.len(), 2);
+    /// tx.rollback();
+    ///
+    /// assert_eq!(arena.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// Returns true if the arena contains no elements, including the changes
+    /// made by this transaction so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    ///
+    /// let mut tx = arena.begin();
+    /// tx.remove(idx);
+    /// assert!(tx.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    /// Keep the changes made by this transaction.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let idx = arena.insert(1);
+    ///
+    /// let mut tx = arena.begin();
+    /// tx.remove(idx);
+    /// tx.commit();
+    ///
+    /// assert!(!arena.contains(idx));
+    /// ```
+    pub fn commit(mut self) {
+        self.journal = None;
+    }
+
+    /// Undo the changes made by this transaction.
+    ///
+    /// Afterwards, the arena is in the same state as when the transaction
+    /// began: the same elements at the same indices, and the same free slots
+    /// to be reused in the same order, so the indices handed out by the
+    /// transaction may be handed out again. This is also what happens when the
+    /// transaction is dropped without being committed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use generational_arena::Arena;
+    ///
+    /// let mut arena = Arena::new();
+    /// let a = arena.insert(1);
+    ///
+    /// let mut tx = arena.begin();
+    /// tx.remove(a);
+    /// let b = tx.insert(2);
+    /// tx.rollback();
+    ///
+    /// assert_eq!(arena[a], 1);
+    /// assert!(!arena.contains(b));
+    ///
+    /// // The free slots are reused in the same order as before.
+    /// arena.remove(a);
+    /// assert_eq!(arena.insert(3), b);
+    /// ```
+    pub fn rollback(self) {
+        // Dropping the transaction rolls it back.
+    }
+}
+
+impl<'a, T, K, P: ReusePolicy> Drop for Transaction<'a, T, K, P> {
+    fn drop(&mut self) {
+        // The arena still holds the journal if a change panicked.
+        let journal = self.journal.take().or_else(|| self.arena.journal.take());
+        if let Some(journal) = journal {
+            journal.rollback(self.arena);
+        }
+    }
+}
+
+impl<'a, T, K, P: ReusePolicy> fmt::Debug for Transaction<'a, T, K, P>
+where
+    T: fmt::Debug,
+    K: fmt::Debug,
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("arena", &self.arena)
+            .finish()
+    }
+}
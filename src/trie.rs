@@ -0,0 +1,265 @@
+//! A persistent vector, stored as a radix trie of reference-counted nodes.
+//!
+//! Used by `ArenaSnapshot` and `PersistentArena` to share slots between
+//! versions of an arena.
+
+use super::{Arc, Vec};
+use core::cmp;
+use core::mem;
+use core::ops::Range;
+use core::slice;
+
+// The number of bits of a slot index that each level of the trie consumes.
+const BITS: u32 = 5;
+// The number of slots in each leaf, and the number of children of each
+// branch.
+pub(crate) const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+/// A vector whose clones share their nodes: cloning it takes constant time,
+/// and changing a slot of one clone only copies the nodes on the path to that
+/// slot, of which there are O(log n).
+#[derive(Debug)]
+pub(crate) struct Trie<X> {
+    root: Arc<Node<X>>,
+    len: usize,
+    // The number of index bits below the root's level: 0 if the root is a
+    // leaf. A node at level `shift` holds up to `WIDTH << shift` slots.
+    shift: u32,
+}
+
+#[derive(Clone, Debug)]
+enum Node<X> {
+    Leaf(Vec<X>),
+    Branch(Vec<Arc<Node<X>>>),
+}
+
+impl<X> Trie<X> {
+    pub(crate) fn new() -> Self {
+        Trie {
+            root: Arc::new(Node::Leaf(Vec::new())),
+            len: 0,
+            shift: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, i: usize) -> Option<&X> {
+        if i >= self.len {
+            return None;
+        }
+        let mut node = &*self.root;
+        let mut shift = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => {
+                    node = &children[(i >> shift) & MASK];
+                    shift -= BITS;
+                }
+                Node::Leaf(slots) => return slots.get(i & MASK),
+            }
+        }
+    }
+
+    pub(crate) fn iter(&self) -> Iter<'_, X> {
+        let mut iter = Iter {
+            branches: Vec::new(),
+            leaf: [].iter(),
+        };
+        match &*self.root {
+            Node::Leaf(slots) => iter.leaf = slots.iter(),
+            Node::Branch(children) => iter.branches.push(children.iter()),
+        }
+        iter
+    }
+
+    /// Calls `f` with ranges of slots, among the first `end`, outside of which
+    /// this trie and `other` share all their nodes, so that their slots are
+    /// the same. The ranges are in ascending order, and cover whole leaves.
+    ///
+    /// Takes time proportional to the number of nodes that differ.
+    pub(crate) fn diff(&self, other: &Self, end: usize, mut f: impl FnMut(Range<usize>)) {
+        let end = cmp::min(end, cmp::min(self.len, other.len));
+        if end == 0 {
+            return;
+        }
+        // The first slots of a taller trie are in the first child of its root.
+        let (mut a, mut b) = (&self.root, &other.root);
+        let shift = cmp::min(self.shift, other.shift);
+        for _ in (shift..self.shift).step_by(BITS as usize) {
+            a = a.first_child();
+        }
+        for _ in (shift..other.shift).step_by(BITS as usize) {
+            b = b.first_child();
+        }
+        diff_nodes(a, b, shift, 0, end, &mut f);
+    }
+}
+
+impl<X: Clone> Trie<X> {
+    /// Get an exclusive reference to slot `i`, copying the nodes on the path
+    /// to it first if another trie shares them.
+    pub(crate) fn get_mut(&mut self, i: usize) -> Option<&mut X> {
+        if i >= self.len {
+            return None;
+        }
+        let mut node = Arc::make_mut(&mut self.root);
+        let mut shift = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => {
+                    node = Arc::make_mut(&mut children[(i >> shift) & MASK]);
+                    shift -= BITS;
+                }
+                Node::Leaf(slots) => return slots.get_mut(i & MASK),
+            }
+        }
+    }
+
+    pub(crate) fn push(&mut self, x: X) {
+        if self.len >> self.shift == WIDTH {
+            // The trie is full: make its root the first child of a new root.
+            let old = mem::replace(&mut self.root, Arc::new(Node::Branch(Vec::new())));
+            let mut children = Vec::with_capacity(WIDTH);
+            children.push(old);
+            self.root = Arc::new(Node::Branch(children));
+            self.shift += BITS;
+        }
+        let i = self.len;
+        let mut node = Arc::make_mut(&mut self.root);
+        let mut shift = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => {
+                    let c = (i >> shift) & MASK;
+                    if c == children.len() {
+                        children.push(Arc::new(if shift == BITS {
+                            Node::Leaf(Vec::with_capacity(WIDTH))
+                        } else {
+                            Node::Branch(Vec::with_capacity(WIDTH))
+                        }));
+                    }
+                    node = Arc::make_mut(&mut children[c]);
+                    shift -= BITS;
+                }
+                Node::Leaf(slots) => {
+                    slots.push(x);
+                    break;
+                }
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Drops the slots from `len` onwards.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        if len == 0 {
+            *self = Trie::new();
+            return;
+        }
+        let last = len - 1;
+        while self.shift > 0 && last >> self.shift == 0 {
+            self.root = self.root.first_child().clone();
+            self.shift -= BITS;
+        }
+        let mut node = Arc::make_mut(&mut self.root);
+        let mut shift = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => {
+                    let c = (last >> shift) & MASK;
+                    children.truncate(c + 1);
+                    node = Arc::make_mut(&mut children[c]);
+                    shift -= BITS;
+                }
+                Node::Leaf(slots) => {
+                    slots.truncate((last & MASK) + 1);
+                    break;
+                }
+            }
+        }
+        self.len = len;
+    }
+}
+
+impl<X> Node<X> {
+    fn first_child(&self) -> &Arc<Node<X>> {
+        match self {
+            Node::Branch(children) => &children[0],
+            Node::Leaf(_) => unreachable!("leaves have no children"),
+        }
+    }
+}
+
+/// See `Trie::diff`. `a` and `b` hold the slots from `start` onwards, and are
+/// at level `shift`.
+fn diff_nodes<X>(
+    a: &Arc<Node<X>>,
+    b: &Arc<Node<X>>,
+    shift: u32,
+    start: usize,
+    end: usize,
+    f: &mut impl FnMut(Range<usize>),
+) {
+    if start >= end || Arc::ptr_eq(a, b) {
+        return;
+    }
+    match (&**a, &**b) {
+        (Node::Branch(a), Node::Branch(b)) => {
+            for (c, (a, b)) in a.iter().zip(b).enumerate() {
+                diff_nodes(a, b, shift - BITS, start + (c << shift), end, f);
+            }
+        }
+        _ => f(start..cmp::min(start + WIDTH, end)),
+    }
+}
+
+impl<X> Clone for Trie<X> {
+    fn clone(&self) -> Self {
+        Trie {
+            root: self.root.clone(),
+            len: self.len,
+            shift: self.shift,
+        }
+    }
+}
+
+/// An iterator over the slots of a `Trie`, in order.
+#[derive(Clone, Debug)]
+pub(crate) struct Iter<'a, X> {
+    // The children left to visit at each level above the current leaf.
+    branches: Vec<slice::Iter<'a, Arc<Node<X>>>>,
+    leaf: slice::Iter<'a, X>,
+}
+
+impl<'a, X> Iterator for Iter<'a, X> {
+    type Item = &'a X;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(x) = self.leaf.next() {
+                return Some(x);
+            }
+            loop {
+                match self.branches.last_mut()?.next() {
+                    Some(node) => match &**node {
+                        Node::Leaf(slots) => {
+                            self.leaf = slots.iter();
+                            break;
+                        }
+                        Node::Branch(children) => self.branches.push(children.iter()),
+                    },
+                    None => {
+                        self.branches.pop();
+                    }
+                }
+            }
+        }
+    }
+}
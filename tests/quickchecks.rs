@@ -2,7 +2,10 @@ extern crate generational_arena;
 #[macro_use]
 extern crate quickcheck;
 
-use generational_arena::Arena;
+use generational_arena::persistent::PersistentArena;
+use generational_arena::{
+    Arena, ArenaKey, CompactArena, CompactIndex, Fifo, Index, Lifo, LowestFirst, ReusePolicy,
+};
 use std::collections::BTreeSet;
 use std::iter::FromIterator;
 
@@ -137,13 +140,13 @@ quickcheck! {
 
         // check that the results from get() match get_unknown_check()
         inserted_indices.iter().enumerate().all(|(i, idx)| {
-            let shared_check = if let Some(_) = arena.get(*idx) {
+            let shared_check = if arena.get(*idx).is_some() {
                 let internal_index = idx.into_raw_parts().0;
                 arena.get_unknown_gen(internal_index).is_some() && unknown_gen_indices[i] == internal_index
             } else {
                 true
             };
-            let mut_check = if let Some(_) = arena.get_mut(*idx) {
+            let mut_check = if arena.get_mut(*idx).is_some() {
                 let internal_index = idx.into_raw_parts().0;
                 arena.get_unknown_gen_mut(internal_index).is_some() && unknown_gen_indices[i] == internal_index
             } else {
@@ -193,3 +196,436 @@ quickcheck! {
         }
     }
 }
+
+quickcheck! {
+    fn near_max_generations_never_resurrect(ops: Vec<(bool, usize)>) -> () {
+        let start = CompactIndex::MAX_GENERATION - 2;
+        let mut arena = CompactArena::with_capacity_and_generation(1, start);
+        let mut live_indices = vec![];
+        let mut dead_indices = vec![];
+
+        for (delete, i) in ops {
+            if delete && !live_indices.is_empty() {
+                let i = i % live_indices.len();
+                let (idx, expected) = live_indices.remove(i);
+                assert_eq!(arena.remove(idx).unwrap(), expected);
+                dead_indices.push(idx);
+            } else {
+                live_indices.push((arena.insert(i), i));
+            }
+
+            for (live, expected) in live_indices.iter().cloned() {
+                assert_eq!(*arena.get(live).unwrap(), expected);
+            }
+            for dead in dead_indices.iter().cloned() {
+                assert!(!arena.contains(dead));
+            }
+            assert!(arena.len() + arena.retired_count() <= arena.capacity());
+        }
+    }
+}
+
+quickcheck! {
+    fn compact_keeps_elements_and_stales_old_indices(ops: Vec<(bool, usize)>) -> () {
+        let mut arena = Arena::new();
+        let mut live_indices = vec![];
+        let mut dead_indices = vec![];
+
+        for (delete, i) in ops {
+            if delete && !live_indices.is_empty() {
+                let i = i % live_indices.len();
+                let (idx, _) = live_indices.remove(i);
+                arena.remove(idx);
+                dead_indices.push(idx);
+            } else {
+                live_indices.push((arena.insert(i), i));
+            }
+        }
+
+        arena.compact_with(|old, new| {
+            let live = live_indices.iter_mut().find(|(idx, _)| *idx == old).unwrap();
+            live.0 = new;
+            dead_indices.push(old);
+        });
+
+        assert_eq!(arena.capacity(), arena.len());
+        for (live, expected) in live_indices.iter().cloned() {
+            assert_eq!(*arena.get(live).unwrap(), expected);
+        }
+
+        // Refill the arena and check that no stale index comes back to life.
+        for i in 0..dead_indices.len() {
+            arena.insert(i);
+        }
+        for dead in dead_indices.iter().cloned() {
+            assert!(!arena.contains(dead));
+        }
+    }
+}
+
+/// Checks that `P` reuses free slots in the order of `model`, whose first slot
+/// is the next one reused, after `free(model, slot)` puts freed slots into it.
+fn check_reuse_order<P: ReusePolicy>(ops: Vec<(bool, usize)>, free: fn(&mut Vec<usize>, usize)) {
+    let mut arena = Arena::<usize, Index, P>::with_capacity_and_key(1);
+    let mut model = vec![0];
+    let mut live_indices = vec![];
+
+    for (delete, i) in ops {
+        if delete && !live_indices.is_empty() {
+            let idx: Index = live_indices.remove(i % live_indices.len());
+            arena.remove(idx);
+            free(&mut model, idx.into_raw_parts().0);
+        } else {
+            let capacity = arena.capacity();
+            let idx = arena.insert(i);
+            // New slots are reused in ascending order.
+            model.extend(capacity..arena.capacity());
+            assert_eq!(idx.into_raw_parts().0, model.remove(0));
+            live_indices.push(idx);
+        }
+    }
+}
+
+quickcheck! {
+    fn lifo_reuses_most_recently_freed_slot(ops: Vec<(bool, usize)>) -> () {
+        check_reuse_order::<Lifo>(ops, |model, slot| model.insert(0, slot));
+    }
+
+    fn fifo_reuses_least_recently_freed_slot(ops: Vec<(bool, usize)>) -> () {
+        check_reuse_order::<Fifo>(ops, |model, slot| model.push(slot));
+    }
+
+    fn lowest_first_reuses_lowest_slot(ops: Vec<(bool, usize)>) -> () {
+        check_reuse_order::<LowestFirst>(ops, |model, slot| {
+            let at = model.binary_search(&slot).unwrap_err();
+            model.insert(at, slot);
+        });
+    }
+}
+
+/// Mirrors the insertions and removals on one arena onto another arena with
+/// `insert_at`, and checks that both agree on the elements they hold.
+fn check_insert_at_mirrors<P: ReusePolicy>(ops: Vec<(bool, usize)>) {
+    let mut server = Arena::<usize, Index, Lifo>::with_capacity(1);
+    let mut client = Arena::<usize, Index, P>::with_capacity_and_key(1);
+    let mut live_indices = vec![];
+    let mut dead_indices = vec![];
+
+    for (delete, i) in ops {
+        if delete && !live_indices.is_empty() {
+            let idx = live_indices.remove(i % live_indices.len());
+            assert_eq!(client.remove(idx), server.remove(idx));
+            dead_indices.push(idx);
+        } else {
+            let idx = server.insert(i);
+            assert_eq!(client.insert_at(idx, i), Ok(None));
+            live_indices.push(idx);
+        }
+    }
+
+    assert_eq!(client.len(), server.len());
+    for live in live_indices.iter().cloned() {
+        assert_eq!(client.get(live), server.get(live));
+    }
+    for dead in dead_indices.iter().cloned() {
+        assert!(client.insert_at(dead, 0).is_err());
+    }
+
+    // The free list is intact.
+    let len = client.len();
+    for i in 0..client.capacity() - len {
+        client.try_insert(i).unwrap();
+    }
+    assert!(client.try_insert(0).is_err());
+}
+
+quickcheck! {
+    fn insert_at_mirrors_lifo(ops: Vec<(bool, usize)>) -> () {
+        check_insert_at_mirrors::<Lifo>(ops);
+    }
+
+    fn insert_at_mirrors_fifo(ops: Vec<(bool, usize)>) -> () {
+        check_insert_at_mirrors::<Fifo>(ops);
+    }
+
+    fn insert_at_mirrors_lowest_first(ops: Vec<(bool, usize)>) -> () {
+        check_insert_at_mirrors::<LowestFirst>(ops);
+    }
+}
+
+/// Makes the same changes to an arena in a transaction that is rolled back,
+/// and checks that it ends up identical to a clone taken beforehand: same
+/// elements, and same indices handed out afterwards.
+fn check_rollback_restores<P: ReusePolicy>(setup: Vec<(bool, usize)>, ops: Vec<(u8, usize)>) {
+    let mut arena = Arena::<usize, Index, P>::with_capacity_and_key(1);
+    arena.set_quarantine_len(setup.len() % 3);
+    let mut live_indices = vec![];
+    for (delete, i) in setup {
+        if delete && !live_indices.is_empty() {
+            arena.remove(live_indices.remove(i % live_indices.len()));
+        } else {
+            live_indices.push(arena.insert(i));
+        }
+    }
+    let before = arena.clone();
+
+    let mut tx = arena.begin();
+    for (op, i) in ops {
+        match op % 3 {
+            0 if !live_indices.is_empty() => {
+                tx.remove(live_indices.remove(i % live_indices.len()));
+            }
+            1 if !live_indices.is_empty() => {
+                *tx.get_mut(live_indices[i % live_indices.len()]).unwrap() += 1;
+            }
+            _ => live_indices.push(tx.insert(i)),
+        }
+    }
+    tx.rollback();
+
+    assert_same(arena, before);
+}
+
+/// Checks that two arenas hold the same elements, and hand out the same
+/// indices from now on.
+fn assert_same<P: ReusePolicy>(mut a: Arena<usize, Index, P>, mut b: Arena<usize, Index, P>) {
+    assert_eq!(a.len(), b.len());
+    assert_eq!(a.capacity(), b.capacity());
+    assert_eq!(a.iter().collect::<Vec<_>>(), b.iter().collect::<Vec<_>>());
+    for i in 0..a.capacity() * 2 {
+        let idx = a.insert(i);
+        assert_eq!(b.insert(i), idx);
+        if i % 3 == 0 {
+            assert_eq!(a.remove(idx), b.remove(idx));
+        }
+    }
+}
+
+quickcheck! {
+    fn rollback_restores_lifo(setup: Vec<(bool, usize)>, ops: Vec<(u8, usize)>) -> () {
+        check_rollback_restores::<Lifo>(setup, ops);
+    }
+
+    fn rollback_restores_fifo(setup: Vec<(bool, usize)>, ops: Vec<(u8, usize)>) -> () {
+        check_rollback_restores::<Fifo>(setup, ops);
+    }
+
+    fn rollback_restores_lowest_first(setup: Vec<(bool, usize)>, ops: Vec<(u8, usize)>) -> () {
+        check_rollback_restores::<LowestFirst>(setup, ops);
+    }
+}
+
+/// Makes random changes to an arena, taking snapshots and restoring them
+/// along the way, and checks that restoring a snapshot gives the same arena
+/// as a clone taken at the same time.
+fn check_restore_snapshots<P: ReusePolicy>(ops: Vec<(u8, usize)>) {
+    let mut arena = Arena::<usize, Index, P>::with_capacity_and_key(1);
+    arena.set_quarantine_len(ops.len() % 3);
+    let mut live_indices = vec![];
+    let mut snapshots = vec![];
+
+    for (op, i) in ops {
+        match op % 8 {
+            0 | 1 if !live_indices.is_empty() => {
+                arena.remove(live_indices.swap_remove(i % live_indices.len()));
+            }
+            2 if !live_indices.is_empty() => {
+                arena[live_indices[i % live_indices.len()]] += 1;
+            }
+            3 => snapshots.push((arena.snapshot(), arena.clone(), live_indices.clone())),
+            4 if !snapshots.is_empty() => {
+                let (snapshot, expected, live) = &snapshots[i % snapshots.len()];
+                arena.restore(snapshot);
+                live_indices = live.clone();
+                assert_same(arena.clone(), expected.clone());
+            }
+            5 if i % 4 == 0 => arena.retain(|_, value| *value % 2 == 0),
+            5 if i % 4 == 1 => arena.shrink_to_fit(),
+            6 if i % 4 == 0 => live_indices.extend((0..i % 100).map(|j| arena.insert(j))),
+            _ => live_indices.push(arena.insert(i)),
+        }
+        live_indices.retain(|&idx| arena.contains(idx));
+    }
+}
+
+quickcheck! {
+    fn restore_snapshots_lifo(ops: Vec<(u8, usize)>) -> () {
+        check_restore_snapshots::<Lifo>(ops);
+    }
+
+    fn restore_snapshots_fifo(ops: Vec<(u8, usize)>) -> () {
+        check_restore_snapshots::<Fifo>(ops);
+    }
+
+    fn restore_snapshots_lowest_first(ops: Vec<(u8, usize)>) -> () {
+        check_restore_snapshots::<LowestFirst>(ops);
+    }
+}
+
+quickcheck! {
+    fn persistent_arena_mirrors_arena(ops: Vec<(u8, usize)>) -> () {
+        let mut arena = Arena::new();
+        let mut version = PersistentArena::new();
+        let mut versions = vec![];
+        let mut live_indices = vec![];
+
+        for (op, i) in ops {
+            match op % 4 {
+                0 if !live_indices.is_empty() => {
+                    let idx = live_indices.swap_remove(i % live_indices.len());
+                    let (next, value) = version.remove(idx).unwrap();
+                    assert_eq!(arena.remove(idx), Some(value));
+                    assert!(next.remove(idx).is_none());
+                    version = next;
+                }
+                1 if !live_indices.is_empty() => {
+                    let idx = live_indices[i % live_indices.len()];
+                    arena[idx] = i;
+                    version = version.update(idx, |value| *value = i).unwrap();
+                }
+                3 if i % 4 == 0 => {
+                    for j in 0..i % 1500 {
+                        let (next, idx) = version.insert(j);
+                        assert_eq!(arena.insert(j), idx);
+                        live_indices.push(idx);
+                        version = next;
+                    }
+                }
+                _ => {
+                    let (next, idx) = version.insert(i);
+                    assert_eq!(arena.insert(i), idx);
+                    live_indices.push(idx);
+                    version = next;
+                }
+            }
+            versions.push((version.clone(), arena.clone()));
+        }
+
+        // Old versions are left untouched.
+        for (version, arena) in versions {
+            assert_eq!(version.len(), arena.len());
+            assert_eq!(version.iter().collect::<Vec<_>>(), arena.iter().collect::<Vec<_>>());
+        }
+    }
+}
+
+quickcheck! {
+    fn changes_match_elements_since_checkpoint(ops: Vec<(u8, usize)>) -> () {
+        let mut arena = Arena::new();
+        arena.set_track_changes(true);
+        let mut live_indices = vec![];
+        let mut snapshots = vec![];
+        let mut checkpoint = arena.clone();
+
+        for (op, i) in ops {
+            match op % 12 {
+                0 | 1 if !live_indices.is_empty() => {
+                    arena.remove(live_indices.swap_remove(i % live_indices.len()));
+                }
+                2 if !live_indices.is_empty() => {
+                    arena[live_indices[i % live_indices.len()]] += 1;
+                }
+                3 if live_indices.len() > 1 => {
+                    let (a, b) = (live_indices[0], live_indices[i % live_indices.len()]);
+                    if a != b {
+                        let (a, b) = arena.get2_mut(a, b);
+                        *a.unwrap() += *b.unwrap();
+                    }
+                }
+                4 if i % 4 == 0 => arena.retain(|_, value| *value % 2 == 0),
+                5 if i % 8 == 0 => arena.clear(),
+                6 if i % 4 == 0 => {
+                    for (_, value) in arena.iter_mut() {
+                        *value += 1;
+                    }
+                }
+                7 => snapshots.push(arena.snapshot()),
+                8 if !snapshots.is_empty() => arena.restore(&snapshots[i % snapshots.len()]),
+                9 => {
+                    let mut tx = arena.begin();
+                    tx.insert(i);
+                    if let Some(&idx) = live_indices.first() {
+                        tx.remove(idx);
+                    }
+                }
+                10 if i % 4 == 0 => {
+                    let changes = arena.checkpoint().unwrap();
+                    assert_changes(&checkpoint, &arena, &changes);
+                    checkpoint = arena.clone();
+                }
+                _ => live_indices.push(arena.insert(i)),
+            }
+            live_indices = arena.iter().map(|(idx, _)| idx).collect();
+            assert_changes(&checkpoint, &arena, arena.changes().unwrap());
+        }
+    }
+}
+
+/// Checks that `changes` accounts for every difference between `before` and
+/// `after`.
+fn assert_changes(
+    before: &Arena<usize>,
+    after: &Arena<usize>,
+    changes: &generational_arena::changes::Changes,
+) {
+    let live = |arena: &Arena<usize>| arena.iter().map(|(idx, _)| idx).collect::<BTreeSet<_>>();
+    let (before_live, after_live) = (live(before), live(after));
+    assert_eq!(changes.added(), &after_live.difference(&before_live).cloned().collect());
+    assert_eq!(changes.removed(), &before_live.difference(&after_live).cloned().collect());
+    let kept: BTreeSet<_> = before_live.intersection(&after_live).cloned().collect();
+    for idx in &kept {
+        if before[*idx] != after[*idx] {
+            assert!(changes.modified().contains(idx));
+        }
+    }
+    assert!(changes.modified().is_subset(&kept));
+}
+
+/// Makes random changes to an arena, and checks that applying the delta
+/// between each state and the next to a replica keeps it identical.
+fn check_apply_delta_reproduces<P: ReusePolicy>(ops: Vec<(u8, usize)>) {
+    let mut arena = Arena::<usize, Index, P>::with_capacity_and_key(1);
+    arena.set_quarantine_len(ops.len() % 3);
+    let mut replica = arena.clone();
+    let mut live_indices = vec![];
+
+    for (op, i) in ops {
+        let old = arena.clone();
+        match op % 8 {
+            0 | 1 if !live_indices.is_empty() => {
+                arena.remove(live_indices.swap_remove(i % live_indices.len()));
+            }
+            2 if !live_indices.is_empty() => {
+                arena[live_indices[i % live_indices.len()]] += 1;
+            }
+            3 if i % 4 == 0 => arena.retain(|_, value| *value % 2 == 0),
+            4 if i % 4 == 0 => {
+                arena.compact();
+            }
+            5 => {
+                arena.reserve_index();
+            }
+            _ => live_indices.push(arena.insert(i)),
+        }
+        live_indices = arena.iter().map(|(idx, _)| idx).collect();
+        assert_eq!(replica.apply_delta(Arena::diff(&old, &arena)), Ok(()));
+        assert_eq!(replica.len(), arena.len());
+        assert_eq!(replica.capacity(), arena.capacity());
+        assert_eq!(replica.iter().collect::<Vec<_>>(), arena.iter().collect::<Vec<_>>());
+    }
+    assert_same(replica, arena);
+}
+
+quickcheck! {
+    fn apply_delta_reproduces_lifo(ops: Vec<(u8, usize)>) -> () {
+        check_apply_delta_reproduces::<Lifo>(ops);
+    }
+
+    fn apply_delta_reproduces_fifo(ops: Vec<(u8, usize)>) -> () {
+        check_apply_delta_reproduces::<Fifo>(ops);
+    }
+
+    fn apply_delta_reproduces_lowest_first(ops: Vec<(u8, usize)>) -> () {
+        check_apply_delta_reproduces::<LowestFirst>(ops);
+    }
+}
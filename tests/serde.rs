@@ -1,12 +1,16 @@
 #![cfg(feature = "serde")]
 
-extern crate generational_arena;
 #[macro_use]
+extern crate generational_arena;
 extern crate serde;
 extern crate bincode;
 extern crate serde_test;
 
-use generational_arena::{Arena, Index};
+use generational_arena::delta::ArenaDelta;
+use generational_arena::{
+    Arena, CompactArena, CompactIndex, DeltaError, Fifo, Index, Lifo, LowestFirst, TypedArena,
+    TypedIndex,
+};
 use serde::{Deserialize, Serialize};
 use serde_test::{assert_ser_tokens, Token};
 use std::iter::FromIterator;
@@ -54,6 +58,113 @@ fn deserialized_index_can_be_used_in_the_same_way_as_original_index() {
     }
 }
 
+#[test]
+fn typed_index_has_same_format_as_index() {
+    let mut arena = TypedArena::with_key();
+    arena.insert("apple");
+    let idx = arena.insert("banana");
+    let raw: Index = idx.into();
+
+    let bytes = bincode::serialize(&idx).expect("index must be serialized");
+    assert_eq!(bytes, bincode::serialize(&raw).expect("index must be serialized"));
+    let de_idx = bincode::deserialize::<TypedIndex<&str>>(&bytes).expect("index must be deserialized");
+    assert_eq!(de_idx, idx);
+
+    let bytes = bincode::serialize(&arena).expect("arena must be serialized");
+    let de_arena = bincode::deserialize::<TypedArena<&str>>(&bytes).expect("arena must be deserialized");
+    assert_eq!(de_arena.get(de_idx), Some(&"banana"));
+}
+
+new_key_type! {
+    struct NodeId;
+}
+
+#[test]
+fn custom_key_has_same_format_as_index() {
+    let mut arena = Arena::<&str, NodeId>::with_key();
+    let idx = arena.insert("apple");
+    let raw: Index = idx.into();
+
+    let bytes = bincode::serialize(&idx).expect("key must be serialized");
+    assert_eq!(bytes, bincode::serialize(&raw).expect("index must be serialized"));
+    let de_idx = bincode::deserialize::<NodeId>(&bytes).expect("key must be deserialized");
+    assert_eq!(arena.get(de_idx), Some(&"apple"));
+}
+
+#[test]
+fn compact_index_can_be_serialized_and_deserialized() {
+    let mut arena = CompactArena::with_key();
+    arena.insert("apple");
+    let idx = arena.insert("banana");
+
+    assert_ser_tokens(
+        &idx,
+        &[
+            Token::Tuple { len: 2 },
+            Token::U32(1),
+            Token::U32(0),
+            Token::TupleEnd,
+        ],
+    );
+    let bytes = bincode::serialize(&idx).expect("index must be serialized");
+    let de_idx = bincode::deserialize::<CompactIndex>(&bytes).expect("index must be deserialized");
+    assert_eq!(arena.get(de_idx), Some(&"banana"));
+}
+
+#[test]
+fn compact_arena_retires_slots_on_generation_overflow() {
+    let seq = vec![Some((u64::from(u32::MAX - 1), "foo")), None];
+    let bytes = bincode::serialize(&seq).expect("vec must be serialized");
+    let mut arena =
+        bincode::deserialize::<CompactArena<&str>>(&bytes).expect("arena must be deserialized");
+    // The free slot gets the largest generation, so it can still be used.
+    assert_eq!(arena.retired_count(), 0);
+
+    let idx = CompactIndex::from_raw_parts(0, u32::MAX - 1);
+    assert_eq!(arena.get(idx), Some(&"foo"));
+    assert_eq!(arena.remove(idx), Some("foo"));
+    assert_eq!(arena.retired_count(), 1);
+    let bar = arena.try_insert("bar").unwrap();
+    assert_eq!(bar, CompactIndex::from_raw_parts(1, u32::MAX - 1));
+    assert!(arena.try_insert("baz").is_err());
+    assert_eq!(arena.insert("baz"), CompactIndex::from_raw_parts(2, u32::MAX - 1));
+}
+
+#[test]
+fn out_of_range_generations_are_rejected() {
+    let bytes = bincode::serialize(&(0usize, u64::MAX)).expect("tuple must be serialized");
+    assert!(bincode::deserialize::<Index>(&bytes).is_err());
+
+    let bytes = bincode::serialize(&(0u32, u32::MAX)).expect("tuple must be serialized");
+    assert!(bincode::deserialize::<CompactIndex>(&bytes).is_err());
+
+    let seq = vec![Some((u64::from(u32::MAX), "foo"))];
+    let bytes = bincode::serialize(&seq).expect("vec must be serialized");
+    assert!(bincode::deserialize::<CompactArena<&str>>(&bytes).is_err());
+    assert!(bincode::deserialize::<Arena<&str>>(&bytes).is_ok());
+}
+
+#[test]
+fn reuse_policy_has_same_format() {
+    let mut arena = Arena::<_, Index, LowestFirst>::with_capacity_and_key(4);
+    let idxs: Vec<_> = (0..4).map(|i| arena.insert(i)).collect();
+    arena.remove(idxs[3]);
+    arena.remove(idxs[1]);
+
+    let bytes = bincode::serialize(&arena).expect("arena must be serialized");
+    let lifo = bincode::deserialize::<Arena<i32>>(&bytes).expect("arena must be deserialized");
+    assert_eq!(bincode::serialize(&lifo).expect("arena must be serialized"), bytes);
+
+    let mut de_arena = bincode::deserialize::<Arena<i32, Index, LowestFirst>>(&bytes)
+        .expect("arena must be deserialized");
+    arena.remove(idxs[2]);
+    de_arena.remove(idxs[2]);
+    for arena in &mut [arena, de_arena] {
+        let slots: Vec<_> = (0..3).map(|i| arena.insert(i).into_raw_parts().0).collect();
+        assert_eq!(slots, [1, 2, 3]);
+    }
+}
+
 #[test]
 fn sparse_deserialized_arena_can_use_whole_elements_in_free_list() {
     let capacity = 100;
@@ -145,10 +256,10 @@ fn arena_from_iter_can_be_serialized_and_deserialized_without_panic() {
 
     let mut vec = vec![0usize];
     let x = vec.drain(..);
-    let mut arena_in = Arena::from_iter(x);
+    let arena_in = Arena::from_iter(x);
 
     let ser = serde_yaml::to_string(&arena_in).unwrap();
-    let arena_out: Arena<usize> = serde_yaml::from_str(&ser).unwrap();
+    let _arena_out: Arena<usize> = serde_yaml::from_str(&ser).unwrap();
 }
 
 /// Arena wrapper struct for comparing two arenas
@@ -158,7 +269,7 @@ fn arena_from_iter_can_be_serialized_and_deserialized_without_panic() {
 #[derive(Debug, Serialize, Deserialize)]
 struct ArenaCompare<T>(Arena<T>);
 
-impl<'a, T> PartialEq for ArenaCompare<T>
+impl<T> PartialEq for ArenaCompare<T>
 where
     T: PartialEq,
 {
@@ -186,3 +297,154 @@ where
     assert_ser_tokens(value, tokens);
     assert_de_tokens(value, tokens);
 }
+
+#[test]
+fn reserved_slots_are_serialized_as_vacant() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.reserve_index();
+    arena.insert("b");
+    assert_ser_tokens(
+        &arena,
+        &[
+            Token::Seq { len: Some(2) },
+            Token::None,
+            Token::Some,
+            Token::Tuple { len: 2 },
+            Token::U64(0),
+            Token::BorrowedStr("b"),
+            Token::TupleEnd,
+            Token::SeqEnd,
+        ],
+    );
+
+    let bytes = bincode::serialize(&arena).expect("arena must be serialized");
+    let mut de_arena =
+        bincode::deserialize::<Arena<&str>>(&bytes).expect("arena must be deserialized");
+    assert_eq!(de_arena.fill(a, "a"), Err("a"));
+    assert_eq!(de_arena.len(), 1);
+}
+
+#[test]
+fn arena_delta_can_be_serialized_and_deserialized() {
+    let mut server = Arena::<String, Index, LowestFirst>::with_capacity_and_key(2);
+    server.set_quarantine_len(1);
+    let a = server.insert("a".to_string());
+    server.insert("b".to_string());
+    let mut client = server.clone();
+
+    let old = server.clone();
+    server.remove(a);
+    let c = server.insert("c".to_string());
+    server.reserve_index();
+    let delta = Arena::diff(&old, &server);
+
+    let bytes = bincode::serialize(&delta).unwrap();
+    let yaml = serde_yaml::to_string(&delta).unwrap();
+    let de_deltas: Vec<ArenaDelta<String, Index, LowestFirst>> = vec![
+        bincode::deserialize(&bytes).unwrap(),
+        serde_yaml::from_str(&yaml).unwrap(),
+    ];
+    for de_delta in de_deltas {
+        let mut client = client.clone();
+        client.apply_delta(de_delta).unwrap();
+        assert_eq!(client[c], "c");
+        assert_eq!(client.len(), server.len());
+        assert_eq!(client.capacity(), server.capacity());
+        let mut server = server.clone();
+        for i in 0..4 {
+            assert_eq!(client.insert(i.to_string()), server.insert(i.to_string()));
+        }
+    }
+
+    client.apply_delta(delta).unwrap();
+    assert!(!client.contains(a));
+}
+
+#[test]
+fn invalid_arena_deltas_are_rejected() {
+    let mut arena = Arena::new();
+    let old = arena.clone();
+    arena.insert(42);
+    let delta = Arena::diff(&old, &arena);
+    let bytes = bincode::serialize(&delta).unwrap();
+
+    // Shrinking the capacity leaves the changed slot out of bounds.
+    let mut shrunk = bytes.clone();
+    shrunk[16..24].copy_from_slice(&0u64.to_le_bytes());
+    assert!(bincode::deserialize::<ArenaDelta<i32>>(&shrunk).is_err());
+    assert!(bincode::deserialize::<ArenaDelta<i32>>(&bytes).is_ok());
+
+    // Shrinking the old arena's capacity leaves new slots out of the delta.
+    let mut missing = bytes.clone();
+    missing[..8].copy_from_slice(&0u64.to_le_bytes());
+    assert!(bincode::deserialize::<ArenaDelta<i32>>(&missing).is_err());
+
+    // Generations and the capacity must fit the index type.
+    let mut too_old = bytes.clone();
+    too_old[41..49].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(bincode::deserialize::<ArenaDelta<i32>>(&too_old).is_err());
+    let mut too_old = bytes.clone();
+    too_old[41..49].copy_from_slice(&(u32::MAX as u64).to_le_bytes());
+    assert!(bincode::deserialize::<ArenaDelta<i32>>(&too_old).is_ok());
+    assert!(bincode::deserialize::<ArenaDelta<i32, CompactIndex>>(&too_old).is_err());
+    let mut too_big = bytes.clone();
+    too_big[..8].copy_from_slice(&(u32::MAX as u64 + 2).to_le_bytes());
+    too_big[16..24].copy_from_slice(&(u32::MAX as u64 + 2).to_le_bytes());
+    assert!(bincode::deserialize::<ArenaDelta<i32>>(&too_big).is_ok());
+    assert!(bincode::deserialize::<ArenaDelta<i32, CompactIndex>>(&too_big).is_err());
+    assert!(bincode::deserialize::<ArenaDelta<i32, CompactIndex>>(&bytes).is_ok());
+}
+
+#[test]
+fn inconsistent_arena_deltas_are_not_applied() {
+    let mut arena = Arena::with_capacity(2);
+    let old = arena.clone();
+    arena.insert(42);
+    let bytes = bincode::serialize(&Arena::diff(&old, &arena)).unwrap();
+    let end = bytes.len();
+
+    // The free list head names the occupied slot.
+    let mut occupied_head = bytes.clone();
+    occupied_head[64..72].copy_from_slice(&0u64.to_le_bytes());
+    // The length and the number of retired slots do not match the slots.
+    let mut wrong_len = bytes.clone();
+    wrong_len[end - 16..end - 8].copy_from_slice(&2u64.to_le_bytes());
+    let mut wrong_retired = bytes.clone();
+    wrong_retired[end - 8..].copy_from_slice(&1u64.to_le_bytes());
+
+    for bytes in [occupied_head, wrong_len, wrong_retired] {
+        let delta: ArenaDelta<i32> = bincode::deserialize(&bytes).unwrap();
+        let mut replica = old.clone();
+        assert_eq!(replica.apply_delta(delta), Err(DeltaError::Invalid));
+        assert!(replica.is_empty());
+        assert_eq!(replica.capacity(), 2);
+    }
+    let mut replica = old.clone();
+    assert_eq!(replica.apply_delta(bincode::deserialize(&bytes).unwrap()), Ok(()));
+    assert_eq!(replica.len(), 1);
+}
+
+#[test]
+fn arena_deltas_of_other_reuse_policies_are_rejected() {
+    let mut lifo = Arena::<i32, Index, Lifo>::with_capacity_and_key(2);
+    let mut lowest_first = Arena::<i32, Index, LowestFirst>::with_capacity_and_key(2);
+    let old = lifo.clone();
+    let a = lifo.insert(1);
+    lifo.insert(2);
+    lifo.remove(a);
+    let lifo_bytes = bincode::serialize(&Arena::diff(&old, &lifo)).unwrap();
+    let old = lowest_first.clone();
+    let a = lowest_first.insert(1);
+    lowest_first.insert(2);
+    lowest_first.remove(a);
+    let lowest_first_bytes = bincode::serialize(&Arena::diff(&old, &lowest_first)).unwrap();
+
+    assert!(bincode::deserialize::<ArenaDelta<i32, Index, Lifo>>(&lifo_bytes).is_ok());
+    assert!(bincode::deserialize::<ArenaDelta<i32, Index, Fifo>>(&lifo_bytes).is_err());
+    assert!(bincode::deserialize::<ArenaDelta<i32, Index, LowestFirst>>(&lifo_bytes).is_err());
+    let lowest_first_ok =
+        bincode::deserialize::<ArenaDelta<i32, Index, LowestFirst>>(&lowest_first_bytes);
+    assert!(lowest_first_ok.is_ok());
+    assert!(bincode::deserialize::<ArenaDelta<i32, Index, Lifo>>(&lowest_first_bytes).is_err());
+    assert!(bincode::deserialize::<ArenaDelta<i32, Index, Fifo>>(&lowest_first_bytes).is_err());
+}
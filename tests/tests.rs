@@ -1,5 +1,12 @@
+#[macro_use]
 extern crate generational_arena;
-use generational_arena::Arena;
+use generational_arena::commands::Commands;
+use generational_arena::entry::Entry;
+use generational_arena::persistent::PersistentArena;
+use generational_arena::{
+    Arena, ArenaKey, CompactArena, CompactIndex, DeltaError, Fifo, GetManyError, Index,
+    InsertAtError, LookupError, LowestFirst, TryReserveError, TypedArena, TypedIndex,
+};
 use std::collections::BTreeSet;
 
 #[test]
@@ -193,7 +200,7 @@ fn index_deleted_item() {
     let mut arena = Arena::new();
     let idx = arena.insert(42);
     arena.remove(idx);
-    arena[idx];
+    let _ = arena[idx];
 }
 
 #[test]
@@ -360,3 +367,1456 @@ fn clone_from() {
         assert_eq!(v1, v2);
     }
 }
+
+#[test]
+fn typed_index() {
+    let mut arena: TypedArena<&str> = TypedArena::with_capacity_and_key(2);
+    let a = arena.insert("a");
+    let b = arena.insert_with(|idx| {
+        assert_ne!(idx, a);
+        "b"
+    });
+    assert_eq!(arena[a], "a");
+    assert_eq!(arena.get(b), Some(&"b"));
+    {
+        let (item_a, item_b) = arena.get2_mut(a, b);
+        *item_a.unwrap() = "c";
+        *item_b.unwrap() = "d";
+    }
+    assert_eq!(arena.iter().map(|(idx, _)| idx).collect::<Vec<_>>(), vec![a, b]);
+    assert_eq!(arena.remove(a), Some("c"));
+    assert!(!arena.contains(a));
+    assert!(arena.contains(b));
+}
+
+#[test]
+fn typed_index_raw_parts() {
+    let mut arena = TypedArena::with_key();
+    let idx = arena.insert(42);
+    let raw: Index = idx.into();
+    assert_eq!(raw.into_raw_parts(), idx.into_raw_parts());
+    let (k, g) = idx.into_raw_parts();
+    assert_eq!(arena[TypedIndex::from_raw_parts(k, g)], 42);
+    assert_eq!(arena[TypedIndex::from(raw)], 42);
+}
+
+new_key_type! {
+    struct NodeId;
+}
+
+#[test]
+fn custom_key_type() {
+    let mut arena = Arena::<usize, NodeId>::with_capacity_and_key(2);
+    let a: NodeId = arena.insert(1);
+    let b = arena.insert_with(|id| id.into_index().into_raw_parts().0);
+    let c = arena.insert(3);
+    assert_eq!(arena[a], 1);
+    assert_eq!(arena[b], 1);
+    assert_eq!(arena.get_unknown_gen(2), Some((&3, c)));
+
+    arena.retain(|id, _| id != b);
+    assert_eq!(arena.iter().map(|(id, _)| id).collect::<Vec<_>>(), vec![a, c]);
+    assert_eq!(Index::from(a), a.into_index());
+    assert_eq!(NodeId::from_index(c.into_index()), c);
+
+    let drained: Vec<(NodeId, usize)> = arena.drain().collect();
+    assert_eq!(drained, vec![(a, 1), (c, 3)]);
+    assert!(!arena.contains(a));
+}
+
+#[test]
+fn generations_are_per_slot() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.insert(0);
+    let b = arena.insert(1);
+
+    arena.remove(a);
+    let c = arena.insert(2);
+    assert_eq!(c.into_raw_parts(), (0, 1));
+
+    // Removing the element in slot 0 did not age slot 1.
+    arena.remove(b);
+    let d = arena.insert(3);
+    assert_eq!(d.into_raw_parts(), (1, 1));
+    assert!(!arena.contains(a));
+    assert!(!arena.contains(b));
+
+    // Clearing only ages the occupied slots.
+    arena.remove(d);
+    arena.clear();
+    assert_eq!(arena.insert(4).into_raw_parts(), (0, 2));
+    assert_eq!(arena.insert(5).into_raw_parts(), (1, 2));
+}
+
+#[test]
+fn drain_keeps_indices_stale() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.insert(0);
+    arena.remove(a);
+    let b = arena.insert(1);
+    assert_eq!(b.into_raw_parts(), (0, 1));
+
+    arena.drain();
+    let c = arena.insert(2);
+    assert_eq!(c.into_raw_parts(), (0, 2));
+    assert!(!arena.contains(a));
+    assert!(!arena.contains(b));
+}
+
+#[test]
+fn compact_index() {
+    assert_eq!(std::mem::size_of::<CompactIndex>(), 8);
+
+    let mut arena = CompactArena::with_capacity_and_key(1);
+    let a = arena.insert(1);
+    arena.remove(a);
+    let b = arena.insert(2);
+    assert_eq!(b.into_raw_parts(), (0, 1));
+    let (k, g) = b.into_raw_parts();
+    assert_eq!(arena[CompactIndex::from_raw_parts(k, g)], 2);
+    assert_eq!(Index::from(b).into_raw_parts(), (0, 1));
+    assert!(!arena.contains(a));
+}
+
+#[test]
+#[should_panic(expected = "does not fit")]
+fn compact_index_from_out_of_range_index() {
+    CompactIndex::from_index(Index::from_raw_parts(0, 1 << 32));
+}
+
+#[test]
+fn option_index_has_no_overhead() {
+    use std::mem::size_of;
+    assert_eq!(size_of::<Option<Index>>(), size_of::<Index>());
+    assert_eq!(size_of::<Option<TypedIndex<String>>>(), size_of::<Index>());
+    assert_eq!(size_of::<Option<NodeId>>(), size_of::<Index>());
+    assert_eq!(size_of::<Option<CompactIndex>>(), 8);
+}
+
+#[test]
+fn index_raw_parts_round_trip() {
+    for &(k, g) in &[(0, 0), (7, 1), (usize::MAX, u64::MAX - 1)] {
+        assert_eq!(Index::from_raw_parts(k, g).into_raw_parts(), (k, g));
+    }
+    assert!(Index::from_raw_parts(0, 0) < Index::from_raw_parts(0, 1));
+    assert_eq!(
+        format!("{:?}", Index::from_raw_parts(1, 2)),
+        "Index { index: 1, generation: 2 }"
+    );
+}
+
+#[test]
+#[should_panic]
+fn index_from_raw_parts_with_invalid_generation() {
+    Index::from_raw_parts(0, u64::MAX);
+}
+
+#[test]
+fn retire_slots_on_generation_overflow() {
+    let max = Index::MAX_GENERATION;
+    let mut arena = Arena::<u32>::with_capacity_and_generation(2, max - 1);
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+    arena.remove(a);
+    let c = arena.insert(3);
+    assert_eq!(c.into_raw_parts(), (0, max));
+
+    assert_eq!(arena.remove(c), Some(3));
+    assert_eq!(arena.retired_count(), 1);
+    assert_eq!(arena.len(), 1);
+    assert!(arena.try_insert(4).is_err());
+    assert!(!arena.contains(a));
+    assert!(!arena.contains(c));
+
+    // Growing the arena still works, and never hands out the retired slot.
+    let d = arena.insert(4);
+    assert_eq!(d.into_raw_parts().0, 2);
+    assert_eq!(arena.iter().count(), 2);
+
+    // `clear` retires the slots of elements with the largest generation and
+    // keeps already retired slots retired.
+    arena.remove(b);
+    let e = arena.insert(5);
+    assert_eq!(e.into_raw_parts(), (1, max));
+    arena.clear();
+    assert_eq!(arena.retired_count(), 2);
+    assert_eq!(arena.insert(6).into_raw_parts().0, 2);
+    assert!(arena.try_insert(7).is_ok());
+    assert!(arena.try_insert(8).is_err());
+}
+
+#[test]
+fn drain_retired_slots() {
+    let max = CompactIndex::MAX_GENERATION;
+    let mut arena = CompactArena::<u32>::with_capacity_and_generation(1, max);
+    let a = arena.insert(1);
+    arena.remove(a);
+    assert_eq!(arena.retired_count(), 1);
+
+    // No generation is left for slot 0, so it stays retired, like with
+    // `clear`, and can never be used again.
+    assert_eq!(arena.drain().count(), 0);
+    assert_eq!(arena.retired_count(), 1);
+    assert_eq!(arena.capacity(), 1);
+    assert!(arena.try_insert(2).is_err());
+}
+
+#[test]
+fn insert_after_draining_retired_slots() {
+    let max = CompactIndex::MAX_GENERATION as u32;
+    let mut arena = CompactArena::<u32>::with_capacity_and_generation(3, u64::from(max - 1));
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+    let c = arena.insert(3);
+    arena.remove(b);
+    let d = arena.insert(4);
+    assert_eq!(d.into_raw_parts(), (1, max));
+
+    // Slot 1 is retired in place by draining, and slot 2 is dropped.
+    assert_eq!(arena.drain().count(), 3);
+    assert_eq!(arena.retired_count(), 1);
+    assert_eq!(arena.capacity(), 2);
+
+    let e = arena.insert(5);
+    let f = arena.insert(6);
+    assert_eq!(e.into_raw_parts(), (0, max));
+    assert_eq!(f.into_raw_parts(), (2, max));
+    assert!(!arena.contains(a) && !arena.contains(c) && !arena.contains(d));
+    assert_eq!((arena[e], arena[f]), (5, 6));
+}
+
+#[test]
+fn entry() {
+    let mut arena = Arena::new();
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+
+    match arena.entry(a) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.index(), a);
+            assert_eq!(entry.replace(10), 1);
+            assert_eq!(*entry.get(), 10);
+        }
+        Entry::Vacant(_) => panic!("entry for a live index must be occupied"),
+    }
+    assert_eq!(arena[a], 10);
+
+    match arena.entry(b) {
+        Entry::Occupied(entry) => assert_eq!(entry.remove(), 2),
+        Entry::Vacant(_) => panic!("entry for a live index must be occupied"),
+    }
+    assert_eq!(arena.len(), 1);
+
+    // `b` is stale now, and inserting through its entry reuses the slot with a
+    // newer generation.
+    let c = match arena.entry(b) {
+        Entry::Occupied(_) => panic!("entry for a stale index must be vacant"),
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.index(), b);
+            entry.insert(3)
+        }
+    };
+    assert_ne!(b, c);
+    assert_eq!(arena.get(b), None);
+    assert_eq!(arena[c], 3);
+
+    let (d, value) = arena.entry(b).or_insert_with(|| 4);
+    *value += 1;
+    assert_eq!(arena[d], 5);
+    assert_eq!(arena.entry(d).and_modify(|v| *v += 1).or_insert(0), (d, &mut 6));
+}
+
+#[test]
+fn entry_typed() {
+    let mut arena = TypedArena::with_key();
+    let a = arena.insert("a");
+    let (same, value) = arena.entry(a).or_insert("b");
+    assert_eq!((same, *value), (a, "a"));
+}
+
+#[test]
+fn get_many_mut() {
+    let mut arena = Arena::new();
+    let idxs: Vec<_> = (0..5).map(|i| arena.insert(i)).collect();
+
+    for value in arena.get_many_mut([idxs[4], idxs[0], idxs[2]]).unwrap().iter_mut() {
+        **value *= 10;
+    }
+    let values: Vec<_> = idxs.iter().map(|&i| arena[i]).collect();
+    assert_eq!(values, [0, 1, 20, 3, 40]);
+
+    let none: [&mut i32; 0] = arena.get_many_mut([]).unwrap();
+    assert!(none.is_empty());
+
+    assert_eq!(
+        arena.get_many_mut([idxs[1], idxs[3], idxs[1], idxs[3]]).unwrap_err(),
+        GetManyError::Duplicate { first: 0, second: 2 }
+    );
+
+    // A stale index sharing a slot with a live one is reported as not found,
+    // rather than as a duplicate.
+    arena.remove(idxs[3]);
+    let new = arena.insert(5);
+    assert_eq!(
+        arena.get_many_mut([new, idxs[3]]).unwrap_err(),
+        GetManyError::NotFound { position: 1 }
+    );
+
+    let other = Index::from_raw_parts(100, 0);
+    assert_eq!(
+        arena.get_many_mut([idxs[0], other]).unwrap_err(),
+        GetManyError::NotFound { position: 1 }
+    );
+}
+
+#[test]
+fn get_many_unchecked_mut() {
+    let mut arena = Arena::new();
+    let idxs: Vec<_> = (0..5).map(|i| arena.insert(i)).collect();
+
+    for value in arena.get_many_unchecked_mut([idxs[0], idxs[2], idxs[4]]).unwrap().iter_mut() {
+        **value *= 10;
+    }
+    let values: Vec<_> = idxs.iter().map(|&i| arena[i]).collect();
+    assert_eq!(values, [0, 1, 20, 3, 40]);
+
+    arena.remove(idxs[3]);
+    assert_eq!(
+        arena.get_many_unchecked_mut([idxs[1], idxs[3]]).unwrap_err(),
+        GetManyError::NotFound { position: 1 }
+    );
+}
+
+#[test]
+#[should_panic(expected = "strictly ascending order")]
+fn get_many_unchecked_mut_duplicate() {
+    let mut arena = Arena::new();
+    let a = arena.insert(1);
+    let _ = arena.get_many_unchecked_mut([a, a]);
+}
+
+#[test]
+#[should_panic(expected = "strictly ascending order")]
+fn get_many_unchecked_mut_out_of_order() {
+    let mut arena = Arena::new();
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+    let _ = arena.get_many_unchecked_mut([b, a]);
+}
+
+#[test]
+fn get_many_unknown_gen_mut() {
+    let mut arena = Arena::new();
+    let a = arena.insert("a");
+    let b = arena.insert("b");
+    arena.remove(a);
+    let c = arena.insert("c");
+
+    let [(x, i), (y, j)] = arena.get_many_unknown_gen_mut([1, 0]).unwrap();
+    assert_eq!((*x, i), ("b", b));
+    assert_eq!((*y, j), ("c", c));
+
+    assert_eq!(
+        arena.get_many_unknown_gen_mut([0, 2]).unwrap_err(),
+        GetManyError::NotFound { position: 1 }
+    );
+    assert_eq!(
+        arena.get_many_unknown_gen_mut([1, 1]).unwrap_err(),
+        GetManyError::Duplicate { first: 0, second: 1 }
+    );
+}
+
+#[test]
+fn lookup_errors() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.insert(1);
+    assert_eq!(arena.try_get(a), Ok(&1));
+    assert_eq!(arena.try_get_mut(a), Ok(&mut 1));
+
+    let vacant = Index::from_raw_parts(1, 0);
+    assert_eq!(arena.try_get(vacant), Err(LookupError::Vacant { index: 1 }));
+
+    let out_of_bounds = Index::from_raw_parts(2, 0);
+    assert_eq!(
+        arena.try_remove(out_of_bounds),
+        Err(LookupError::OutOfBounds {
+            index: 2,
+            capacity: 2
+        })
+    );
+
+    assert_eq!(arena.try_remove(a), Ok(1));
+    assert_eq!(arena.try_remove(a), Err(LookupError::Vacant { index: 0 }));
+    arena.insert(2);
+    assert_eq!(
+        arena.try_get_mut(a),
+        Err(LookupError::StaleGeneration {
+            expected: 0,
+            found: 1
+        })
+    );
+    assert_eq!(arena.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "No element at index: stale index with generation 0, but the slot holds generation 1")]
+fn index_stale_panics_with_lookup_error() {
+    let mut arena = Arena::new();
+    let a = arena.insert(1);
+    arena.remove(a);
+    arena.insert(2);
+    let _ = arena[a];
+}
+
+#[test]
+fn try_reserve() {
+    let mut arena = Arena::<u64>::with_capacity(1);
+    arena.try_reserve(3).unwrap();
+    assert_eq!(arena.capacity(), 4);
+
+    // Overflows `isize::MAX` bytes, which the allocator rejects.
+    match arena.try_reserve(usize::MAX / 2) {
+        Err(TryReserveError::AllocError(_)) => {}
+        otherwise => panic!("expected an allocation error, got {:?}", otherwise),
+    }
+    assert_eq!(
+        arena.try_reserve(usize::MAX),
+        Err(TryReserveError::CapacityOverflow)
+    );
+    assert_eq!(arena.capacity(), 4);
+    for i in 0..4 {
+        arena.try_insert(i).unwrap();
+    }
+
+    // Pending shared reservations are only added once reserving succeeds.
+    let idx = arena.reserve_index_shared();
+    assert_eq!(
+        arena.try_reserve(usize::MAX),
+        Err(TryReserveError::CapacityOverflow)
+    );
+    assert_eq!(arena.capacity(), 4);
+    arena.try_reserve(1).unwrap();
+    assert_eq!(arena.capacity(), 6);
+    assert_eq!(arena.fill(idx, 4), Ok(()));
+
+    let mut arena = CompactArena::<u64>::with_capacity_and_key(1);
+    assert_eq!(
+        arena.try_reserve(u32::MAX as usize + 1),
+        Err(TryReserveError::CapacityOverflow)
+    );
+    arena.try_reserve(1).unwrap();
+    assert_eq!(arena.capacity(), 2);
+}
+
+#[test]
+fn try_insert_grow() {
+    let mut arena = Arena::with_capacity(1);
+    let a = arena.try_insert_grow(1).unwrap();
+    let b = arena.try_insert_grow(2).unwrap();
+    assert_eq!(arena.capacity(), 2);
+    assert_eq!((arena[a], arena[b]), (1, 2));
+
+    let max = CompactIndex::MAX_GENERATION;
+    let mut arena = CompactArena::<u32>::with_capacity_and_generation(1, max);
+    let a = arena.insert(1);
+    arena.remove(a);
+    arena.drain();
+    let b = arena.try_insert_grow(2).unwrap();
+    assert_eq!(b.into_raw_parts(), (1, max as u32));
+    assert_eq!(arena.capacity(), 2);
+}
+
+#[test]
+fn compact() {
+    let mut arena = Arena::with_capacity(8);
+    let idxs: Vec<_> = (0..8).map(|i| arena.insert(i)).collect();
+    for &i in &[idxs[0], idxs[2], idxs[3], idxs[7]] {
+        arena.remove(i);
+    }
+
+    let remap = arena.compact();
+    assert_eq!(arena.capacity(), 4);
+    assert_eq!(arena.len(), 4);
+    // The highest elements move into the lowest free slots.
+    let old: Vec<_> = remap.iter().map(|&(old, _)| old).collect();
+    assert_eq!(old, [idxs[6], idxs[5], idxs[4]]);
+    let moved: Vec<_> = remap.iter().map(|&(_, new)| arena[new]).collect();
+    assert_eq!(moved, [6, 5, 4]);
+    assert_eq!(arena[idxs[1]], 1);
+    for &i in &[idxs[4], idxs[5], idxs[6]] {
+        assert!(!arena.contains(i));
+    }
+
+    // Slots dropped by compaction do not resurrect stale indices when they are
+    // created again.
+    for i in 0..4 {
+        arena.insert(10 + i);
+    }
+    assert_eq!(arena.capacity(), 8);
+    for &i in &[idxs[4], idxs[5], idxs[6], idxs[7]] {
+        assert!(!arena.contains(i));
+    }
+
+    let mut empty = Arena::<u32>::new();
+    assert!(empty.compact().is_empty());
+    assert_eq!(empty.capacity(), 0);
+    empty.insert(1);
+}
+
+#[test]
+fn compact_keeps_retired_slots() {
+    let max = CompactIndex::MAX_GENERATION;
+    let mut arena = CompactArena::with_capacity_and_generation(3, max);
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+    let c = arena.insert(3);
+    arena.remove(a);
+    arena.remove(b);
+    assert_eq!(arena.retired_count(), 2);
+
+    // There is no free slot to move `c` into.
+    assert!(arena.compact().is_empty());
+    assert_eq!(arena.capacity(), 3);
+    assert_eq!(arena[c], 3);
+}
+
+#[test]
+fn shrink_to() {
+    let mut arena = Arena::with_capacity(8);
+    let idxs: Vec<_> = (0..6).map(|i| arena.insert(i)).collect();
+    arena.remove(idxs[5]);
+    arena.remove(idxs[4]);
+    arena.remove(idxs[1]);
+
+    arena.shrink_to(7);
+    assert_eq!(arena.capacity(), 7);
+    arena.shrink_to(100);
+    assert_eq!(arena.capacity(), 7);
+
+    // The free slot in the middle of the arena is kept.
+    arena.shrink_to_fit();
+    assert_eq!(arena.capacity(), 4);
+    for &i in &[idxs[0], idxs[2], idxs[3]] {
+        assert_eq!(arena[i], i.into_raw_parts().0);
+    }
+
+    // The free list only contains the remaining slots, and the recreated slots
+    // do not resurrect stale indices.
+    let a = arena.try_insert(10).unwrap();
+    assert_eq!(a.into_raw_parts().0, 1);
+    assert!(arena.try_insert(11).is_err());
+    arena.insert(12);
+    arena.insert(13);
+    assert_eq!(arena.capacity(), 8);
+    assert!(!arena.contains(idxs[4]));
+    assert!(!arena.contains(idxs[5]));
+}
+
+#[test]
+fn shrink_to_keeps_free_list_order() {
+    let mut arena = Arena::new();
+    let idxs: Vec<_> = (0..6).map(|i| arena.insert(i)).collect();
+    for &i in &[idxs[1], idxs[3], idxs[5], idxs[2]] {
+        arena.remove(i);
+    }
+
+    // Dropping slot 5 leaves the other free slots to be reused in the same
+    // order as before.
+    arena.shrink_to_fit();
+    assert_eq!(arena.capacity(), 5);
+    let reused: Vec<_> = (0..3).map(|i| arena.insert(i)).collect();
+    let slots: Vec<_> = reused.iter().map(|i| i.into_raw_parts().0).collect();
+    assert_eq!(slots, [2, 3, 1]);
+
+    // Nothing changes when the last slot is occupied.
+    arena.remove(idxs[0]);
+    arena.remove(reused[1]);
+    arena.shrink_to_fit();
+    assert_eq!(arena.capacity(), 5);
+    assert_eq!(arena.insert(10).into_raw_parts().0, 3);
+    assert_eq!(arena.insert(11).into_raw_parts().0, 0);
+}
+
+#[test]
+fn restore_after_shrink_to() {
+    let mut arena = Arena::new();
+    let idxs: Vec<_> = (0..4).map(|i| arena.insert(i)).collect();
+    arena.remove(idxs[3]);
+    let snapshot = arena.snapshot();
+
+    arena.shrink_to_fit();
+    assert_eq!(arena.capacity(), 3);
+    arena.restore(&snapshot);
+    assert_eq!(arena.capacity(), 4);
+    let a = arena.insert(10);
+    assert_eq!(a.into_raw_parts().0, 3);
+    assert_ne!(a, idxs[3]);
+}
+
+#[test]
+fn shrink_to_fit_empty() {
+    let mut arena = Arena::with_capacity(4);
+    let a = arena.insert(1);
+    arena.remove(a);
+    arena.shrink_to_fit();
+    assert_eq!(arena.capacity(), 0);
+    let b = arena.insert(2);
+    assert_ne!(a, b);
+    assert!(!arena.contains(a));
+}
+
+fn slot(i: Index) -> usize {
+    i.into_raw_parts().0
+}
+
+#[test]
+fn reuse_policies() {
+    let mut lifo = Arena::with_capacity(4);
+    let mut fifo = Arena::<_, Index, Fifo>::with_capacity_and_key(4);
+    let mut lowest = Arena::<_, Index, LowestFirst>::with_capacity_and_key(4);
+
+    let lifo_idxs: Vec<_> = (0..4).map(|i| lifo.insert(i)).collect();
+    let fifo_idxs: Vec<_> = (0..4).map(|i| fifo.insert(i)).collect();
+    let lowest_idxs: Vec<_> = (0..4).map(|i| lowest.insert(i)).collect();
+    for &i in &[2, 0, 3] {
+        lifo.remove(lifo_idxs[i]);
+        fifo.remove(fifo_idxs[i]);
+        lowest.remove(lowest_idxs[i]);
+    }
+
+    let lifo_slots: Vec<_> = (0..3).map(|i| slot(lifo.insert(i))).collect();
+    let fifo_slots: Vec<_> = (0..3).map(|i| slot(fifo.insert(i))).collect();
+    let lowest_slots: Vec<_> = (0..3).map(|i| slot(lowest.insert(i))).collect();
+    assert_eq!(lifo_slots, [3, 0, 2]);
+    assert_eq!(fifo_slots, [2, 0, 3]);
+    assert_eq!(lowest_slots, [0, 2, 3]);
+}
+
+#[test]
+fn reuse_policy_survives_clone_and_clear() {
+    let mut arena = Arena::<_, Index, Fifo>::with_capacity_and_key(3);
+    let idxs: Vec<_> = (0..3).map(|i| arena.insert(i)).collect();
+    arena.remove(idxs[1]);
+    arena.remove(idxs[0]);
+
+    let mut clone = arena.clone();
+    assert_eq!(slot(clone.insert(3)), 1);
+    assert_eq!(slot(clone.insert(4)), 0);
+
+    // Slots that are made free all at once are reused in ascending order.
+    arena.clear();
+    let slots: Vec<_> = (0..4).map(|i| slot(arena.insert(i))).collect();
+    assert_eq!(slots, [0, 1, 2, 3]);
+}
+
+#[test]
+fn quarantine() {
+    let mut arena = Arena::with_capacity(4);
+    arena.set_quarantine_len(2);
+    assert_eq!(arena.quarantine_len(), 2);
+    let idxs: Vec<_> = (0..4).map(|i| arena.insert(i)).collect();
+
+    arena.remove(idxs[0]);
+    arena.remove(idxs[1]);
+    // Both slots are in quarantine, so the arena grows.
+    assert!(arena.try_insert(4).is_err());
+    let a = arena.insert(4);
+    assert_eq!(slot(a), 4);
+    assert_eq!(arena.try_get(idxs[0]), Err(LookupError::Vacant { index: 0 }));
+
+    // The third removal releases the oldest quarantined slot.
+    arena.remove(idxs[2]);
+    let used: Vec<_> = (0..4).map(|i| slot(arena.insert(i))).collect();
+    assert_eq!(used, [0, 5, 6, 7]);
+
+    // Shortening the quarantine releases the oldest slots.
+    arena.set_quarantine_len(1);
+    assert_eq!(slot(arena.try_insert(9).unwrap()), 1);
+    assert!(arena.try_insert(10).is_err());
+    arena.set_quarantine_len(0);
+    assert_eq!(slot(arena.try_insert(11).unwrap()), 2);
+}
+
+#[test]
+fn quarantine_with_clear_drain_and_reserve() {
+    let mut arena = Arena::with_capacity(2);
+    arena.set_quarantine_len(8);
+    let a = arena.insert(1);
+    arena.remove(a);
+
+    arena.reserve(1);
+    assert_eq!(slot(arena.try_insert(2).unwrap()), 2);
+    assert_eq!(slot(arena.try_insert(3).unwrap()), 1);
+    assert!(arena.try_insert(4).is_err());
+
+    arena.clear();
+    assert_eq!(arena.quarantine_len(), 8);
+    let slots: Vec<_> = (0..3).map(|i| slot(arena.try_insert(i).unwrap())).collect();
+    assert_eq!(slots, [0, 1, 2]);
+
+    let b = arena.insert(3);
+    arena.remove(b);
+    arena.drain();
+    assert_eq!(slot(arena.insert(4)), 0);
+}
+
+#[test]
+fn quarantine_with_shrink_and_compact() {
+    let mut arena = Arena::with_capacity(4);
+    arena.set_quarantine_len(8);
+    let idxs: Vec<_> = (0..4).map(|i| arena.insert(i)).collect();
+    arena.remove(idxs[3]);
+    arena.remove(idxs[1]);
+
+    // The quarantined slot at the end is dropped, the other one stays in
+    // quarantine.
+    arena.shrink_to_fit();
+    assert_eq!(arena.capacity(), 3);
+    assert!(arena.try_insert(4).is_err());
+
+    let remap = arena.compact();
+    assert_eq!(remap.len(), 1);
+    assert_eq!(arena.capacity(), 2);
+    assert_eq!(arena[remap[0].1], 2);
+}
+
+#[test]
+fn reissue() {
+    let mut arena = Arena::new();
+    let a = arena.insert("a");
+    let b = arena.reissue(a).unwrap();
+    assert_eq!(slot(a), slot(b));
+    assert!(!arena.contains(a));
+    assert_eq!(arena.get(b), Some(&"a"));
+    assert_eq!(arena.len(), 1);
+
+    // The slot's next generation comes after the reissued one.
+    arena.remove(b);
+    let c = arena.insert("c");
+    assert_eq!(slot(c), slot(b));
+    assert!(!arena.contains(a));
+    assert!(!arena.contains(b));
+    assert_eq!(c.into_raw_parts().1, 2);
+}
+
+#[test]
+fn reissue_at_max_generation() {
+    let max = CompactIndex::MAX_GENERATION;
+    let mut arena = CompactArena::with_capacity_and_generation(1, max - 1);
+    let a = arena.insert(1);
+    let b = arena.reissue(a).unwrap();
+    assert_eq!(arena.reissue(b), None);
+    assert_eq!(arena[b], 1);
+}
+
+#[test]
+fn insert_at() {
+    let mut arena = Arena::with_capacity(2);
+
+    // Grows the arena to fit the index.
+    let far = Index::from_raw_parts(5, 3);
+    assert_eq!(arena.insert_at(far, "far"), Ok(None));
+    assert_eq!(arena.capacity(), 6);
+    assert_eq!(arena.len(), 1);
+    assert_eq!(arena[far], "far");
+
+    // The other slots are still free.
+    for _ in 0..5 {
+        let idx = arena.try_insert("near").unwrap();
+        assert_ne!(slot(idx), 5);
+    }
+    assert!(arena.try_insert("full").is_err());
+
+    // A newer generation replaces the element, an older one is rejected.
+    let newer = Index::from_raw_parts(5, 4);
+    assert_eq!(arena.insert_at(newer, "newer"), Ok(Some("far")));
+    assert!(!arena.contains(far));
+    assert_eq!(
+        arena.insert_at(far, "far"),
+        Err(InsertAtError::StaleGeneration {
+            generation: 3,
+            minimum: 4
+        })
+    );
+    assert_eq!(arena.remove(newer), Some("newer"));
+    assert_eq!(
+        arena.insert_at(newer, "newer"),
+        Err(InsertAtError::StaleGeneration {
+            generation: 4,
+            minimum: 5
+        })
+    );
+    assert_eq!(arena.len(), 5);
+}
+
+#[test]
+fn insert_at_quarantined_and_retired_slots() {
+    let mut arena = Arena::with_capacity(2);
+    arena.set_quarantine_len(1);
+    let a = arena.insert(1);
+    arena.remove(a);
+    let b = Index::from_raw_parts(slot(a), 1);
+    assert_eq!(arena.insert_at(b, 2), Ok(None));
+    // The slot left the quarantine, so the next removal does not release it.
+    let c = arena.insert(3);
+    arena.remove(c);
+    assert!(arena.try_insert(4).is_err());
+
+    let max = CompactIndex::MAX_GENERATION;
+    let mut arena = CompactArena::with_capacity_and_generation(1, max);
+    let a = arena.insert(1);
+    arena.remove(a);
+    assert_eq!(arena.insert_at(a, 1), Err(InsertAtError::Retired));
+
+    let mut arena = Arena::<u8>::new();
+    let huge = Index::from_raw_parts(usize::MAX / 2, 0);
+    match arena.insert_at(huge, 1) {
+        Err(InsertAtError::Reserve(_)) => {}
+        otherwise => panic!("expected a reservation error, got {:?}", otherwise),
+    }
+}
+
+#[test]
+fn rejected_insert_at_does_not_grow() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.insert(1);
+    arena.remove(a);
+    arena.shrink_to_fit();
+    assert_eq!(arena.capacity(), 0);
+
+    // The dropped slot's stale index is rejected without growing the arena.
+    assert_eq!(
+        arena.insert_at(a, 2),
+        Err(InsertAtError::StaleGeneration {
+            generation: 0,
+            minimum: 1
+        })
+    );
+    assert_eq!(arena.capacity(), 0);
+    let far = Index::from_raw_parts(3, 0);
+    assert!(arena.insert_at(far, 3).is_err());
+    assert_eq!(arena.capacity(), 0);
+}
+
+#[test]
+fn reserve_index() {
+    let mut arena = Arena::with_capacity(1);
+    let a = arena.reserve_index();
+    assert_eq!(arena.len(), 0);
+    assert!(!arena.contains(a));
+    assert_eq!(arena.try_get(a), Err(LookupError::Reserved { index: 0 }));
+    assert_eq!(arena.remove(a), None);
+    assert_eq!(arena.iter().count(), 0);
+
+    // Reserving grows the arena when needed.
+    let b = arena.reserve_index();
+    let c = arena.insert(3);
+    assert_eq!(arena.capacity(), 4);
+    assert_eq!(arena.len(), 1);
+
+    arena.retain(|_, _| false);
+    assert_eq!(arena.fill(a, 1), Ok(()));
+    assert_eq!(arena.fill(a, 1), Err(1));
+    assert_eq!(arena.len(), 1);
+    assert_eq!(arena[a], 1);
+    assert!(!arena.contains(c));
+
+    assert!(arena.cancel(b));
+    assert_eq!(arena.fill(b, 2), Err(2));
+    let d = arena.insert(4);
+    assert_eq!(slot(d), slot(b));
+    assert_eq!(
+        arena.try_get(b),
+        Err(LookupError::StaleGeneration {
+            expected: 0,
+            found: 1
+        })
+    );
+}
+
+#[test]
+fn reservations_are_cancelled_by_clear_and_drain() {
+    let mut arena = Arena::new();
+    let a = arena.reserve_index();
+    arena.clear();
+    assert_eq!(arena.fill(a, 1), Err(1));
+    let b = arena.insert(2);
+    assert_ne!(a, b);
+
+    let c = arena.reserve_index();
+    assert_eq!(arena.drain().count(), 1);
+    assert_eq!(arena.fill(c, 3), Err(3));
+    assert_eq!(arena.len(), 0);
+    for i in 0..4 {
+        let idx = arena.insert(i);
+        assert_ne!(idx, c);
+    }
+}
+
+#[test]
+fn compact_keeps_reserved_slots() {
+    let mut arena = Arena::with_capacity(4);
+    let a = arena.insert(1);
+    let b = arena.reserve_index();
+    let c = arena.insert(3);
+    arena.remove(a);
+
+    let remap = arena.compact();
+    assert_eq!(remap.len(), 1);
+    assert_eq!(remap[0].0, c);
+    assert_eq!(arena.capacity(), 2);
+    assert_eq!(arena.fill(b, 2), Ok(()));
+}
+
+#[test]
+fn reserve_index_shared_from_threads() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.insert(0);
+
+    let indices: Vec<Index> = std::thread::scope(|s| {
+        let jobs: Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    (0..100)
+                        .map(|_| arena.reserve_index_shared())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        jobs.into_iter()
+            .flat_map(|job| job.join().unwrap())
+            .collect()
+    });
+
+    // Every reservation gets its own slot past the end of the arena.
+    let mut slots: Vec<_> = indices.iter().map(|&idx| slot(idx)).collect();
+    slots.sort_unstable();
+    assert_eq!(slots, (2..402).collect::<Vec<_>>());
+    assert_eq!(arena.capacity(), 2);
+
+    // Growing the arena keeps the reserved slots out of the free list.
+    let b = arena.insert(1);
+    let c = arena.insert(2);
+    assert!(!indices.contains(&b) && !indices.contains(&c));
+    assert!(arena.capacity() > 402);
+
+    for &idx in &indices {
+        assert_eq!(arena.fill(idx, slot(idx)), Ok(()));
+    }
+    assert_eq!(arena.len(), 403);
+    assert_eq!(arena[a], 0);
+    for &idx in &indices {
+        assert_eq!(arena[idx], slot(idx));
+    }
+}
+
+#[test]
+fn shared_reservations_are_flushed_before_changing_slots() {
+    let mut arena = Arena::<usize>::with_capacity(1);
+    let a = arena.reserve_index_shared();
+    assert!(arena.cancel(a));
+    assert_eq!(arena.fill(a, 1), Err(1));
+
+    let b = arena.reserve_index_shared();
+    let cloned = arena.clone();
+    arena.clear();
+    assert_eq!(arena.fill(b, 2), Err(2));
+
+    // The clone does not get the reservation.
+    let mut cloned = cloned;
+    assert_eq!(cloned.fill(b, 2), Err(2));
+    assert_eq!(cloned.capacity(), 2);
+
+    let c = arena.reserve_index_shared();
+    assert_eq!(arena.drain().count(), 0);
+    assert_eq!(arena.fill(c, 3), Err(3));
+
+    let d = arena.reserve_index_shared();
+    arena.shrink_to_fit();
+    assert_eq!(arena.insert_at(d, 4), Ok(None));
+    assert_eq!(arena[d], 4);
+}
+
+#[test]
+fn commands() {
+    let mut arena = Arena::new();
+    let indices: Vec<_> = (0..10).map(|i| arena.insert(i)).collect();
+
+    let mut commands = Commands::new();
+    let (iter, reserver) = arena.iter_mut_with_reserver();
+    for (idx, value) in iter {
+        match *value % 3 {
+            0 => commands.remove(idx),
+            1 => commands.replace(idx, *value * 100),
+            _ => {
+                let child = commands.insert_with(&reserver, |child| *value * 10 + slot(child));
+                commands.replace(child, *value * 10);
+            }
+        }
+        *value += 1000;
+    }
+    assert_eq!(commands.len(), 13);
+
+    // Elements can still be inserted before the commands are applied.
+    let extra = arena.insert(4242);
+
+    assert!(arena.apply(commands).is_empty());
+    assert_eq!(arena.len(), 10 - 4 + 3 + 1);
+    assert_eq!(arena[extra], 4242);
+    for (i, idx) in indices.iter().cloned().enumerate() {
+        match i % 3 {
+            0 => assert!(!arena.contains(idx)),
+            1 => assert_eq!(arena[idx], i * 100),
+            _ => assert_eq!(arena[idx], i + 1000),
+        }
+    }
+    let mut children: Vec<_> = arena.iter().map(|(_, &v)| v).filter(|&v| v < 100).collect();
+    children.sort_unstable();
+    assert_eq!(children, vec![20, 50, 80]);
+}
+
+#[test]
+fn commands_report_stale_indices() {
+    let mut arena = Arena::new();
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+    arena.remove(b);
+
+    let mut commands = Commands::new();
+    let c = commands.insert(&arena.reserver(), 3);
+    commands.remove(c);
+    commands.replace(c, 4);
+    commands.remove(a);
+    commands.remove(a);
+    commands.replace(b, 5);
+    assert_eq!(arena.apply(commands), vec![c, a, b]);
+    assert!(arena.is_empty());
+
+    // Clearing the arena gives up the reservations of pending insertions.
+    let mut commands = Commands::new();
+    let d = commands.insert(&arena.reserver(), 6);
+    arena.clear();
+    assert_eq!(arena.apply(commands), vec![d]);
+    assert!(arena.is_empty());
+}
+
+#[test]
+fn cancelled_commands_release_their_reservations() {
+    let mut arena = Arena::with_capacity(4);
+    let a = arena.insert(0);
+
+    let mut commands = Commands::new();
+    let reserved: Vec<_> = (1..5).map(|i| commands.insert(&arena.reserver(), i)).collect();
+    commands.remove(a);
+    commands.cancel(&mut arena);
+    assert_eq!(arena.capacity(), 8);
+    assert_eq!(arena.len(), 1);
+    assert_eq!(arena[a], 0);
+
+    // The cancelled slots are free again, and their indices are stale.
+    for i in 5..12 {
+        arena.insert(i);
+    }
+    assert_eq!(arena.capacity(), 8);
+    for idx in reserved {
+        assert!(!arena.contains(idx));
+        assert_eq!(arena.fill(idx, 0), Err(0));
+    }
+}
+
+#[test]
+fn transaction() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.insert(String::from("a"));
+    let b = arena.insert(String::from("b"));
+
+    // Committed changes are kept.
+    let c = arena
+        .transaction(|tx| {
+            tx.get_mut(a).unwrap().push('!');
+            assert_eq!(tx.remove(b), Some(String::from("b")));
+            Ok::<_, ()>(tx.insert(String::from("c")))
+        })
+        .unwrap();
+    assert_eq!(arena[a], "a!");
+    assert!(!arena.contains(b));
+    assert_eq!(arena[c], "c");
+
+    // Growing the arena is rolled back too.
+    let mut tx = arena.begin();
+    let new: Vec<_> = (0..10).map(|i| tx.insert(i.to_string())).collect();
+    tx.remove(a);
+    tx.remove(new[3]);
+    tx.rollback();
+    assert_eq!(arena.capacity(), 2);
+    assert_eq!(arena.len(), 2);
+    assert_eq!(arena[a], "a!");
+    for idx in new {
+        assert!(!arena.contains(idx));
+    }
+
+    // Panicking in a transaction rolls it back.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _: Result<(), ()> = arena.transaction(|tx| {
+            tx.remove(c);
+            panic!("oops");
+        });
+    }));
+    assert!(result.is_err());
+    assert_eq!(arena[c], "c");
+
+    // Dropping a transaction without committing it rolls it back.
+    {
+        let mut tx = arena.begin();
+        tx.remove(c);
+    }
+    assert_eq!(arena[c], "c");
+}
+
+#[test]
+fn forgotten_transaction_keeps_its_changes() {
+    let mut arena = Arena::new();
+    let a = arena.insert(1);
+    let b = arena.insert(2);
+
+    let mut tx = arena.begin();
+    *tx.get_mut(a).unwrap() = 10;
+    let c = tx.insert(3);
+    std::mem::forget(tx);
+    assert_eq!((arena[a], arena[c]), (10, 3));
+
+    // The arena works as usual afterwards, and later transactions too.
+    assert_eq!(arena.remove(b), Some(2));
+    assert_eq!(arena.remove(a), Some(10));
+    let mut tx = arena.begin();
+    tx.remove(c);
+    tx.rollback();
+    assert_eq!(arena[c], 3);
+    assert_eq!(arena.len(), 1);
+}
+
+#[test]
+fn restore_only_copies_changed_slots() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Debug)]
+    struct Counted(usize, Rc<Cell<usize>>);
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.1.set(self.1.get() + 1);
+            Counted(self.0, self.1.clone())
+        }
+    }
+
+    let clones = Rc::new(Cell::new(0));
+    let mut arena = Arena::new();
+    let indices: Vec<_> = (0..1000)
+        .map(|i| arena.insert(Counted(i, clones.clone())))
+        .collect();
+
+    let snapshot = arena.snapshot();
+    assert_eq!(clones.get(), 1000);
+
+    arena[indices[1]].0 = 42;
+    arena.remove(indices[2]);
+    let new = arena.insert(Counted(43, clones.clone()));
+    arena.restore(&snapshot);
+    assert_eq!(clones.get(), 1002);
+    assert_eq!(arena[indices[1]].0, 1);
+    assert_eq!(arena[indices[2]].0, 2);
+    assert!(!arena.contains(new));
+
+    // The arena tracks its changes since the restored snapshot again.
+    arena.remove(indices[3]);
+    arena.restore(&snapshot);
+    assert_eq!(clones.get(), 1003);
+    assert_eq!(arena.len(), 1000);
+
+    // Changing many slots at once stops the tracking.
+    for (_, value) in arena.iter_mut() {
+        value.0 += 1;
+    }
+    arena.restore(&snapshot);
+    assert_eq!(clones.get(), 2003);
+    assert_eq!(arena[indices[0]].0, 0);
+
+    // Snapshots share the slots that did not change between them, and
+    // restoring one only copies the chunks of slots in which it differs from
+    // the last one taken or restored.
+    arena[indices[500]].0 = 42;
+    let other = arena.snapshot();
+    assert!(clones.get() - 2003 <= 64);
+    let before = clones.get();
+    arena.restore(&snapshot);
+    assert!(clones.get() - before <= 64);
+    assert_eq!(arena[indices[500]].0, 500);
+    let before = clones.get();
+    arena.remove(indices[0]);
+    arena.restore(&other);
+    assert!(clones.get() - before <= 128);
+    assert_eq!(arena[indices[0]].0, 0);
+    assert_eq!(arena[indices[500]].0, 42);
+
+    // An undo stack costs a few chunks per step, not a copy of the arena.
+    let before = clones.get();
+    let mut undo = Vec::new();
+    for i in 0..100 {
+        undo.push(arena.snapshot());
+        arena[indices[i * 10]].0 += 1;
+    }
+    while let Some(snapshot) = undo.pop() {
+        arena.restore(&snapshot);
+    }
+    assert!(clones.get() - before <= 100 * 128);
+    assert_eq!(arena[indices[0]].0, 0);
+    assert_eq!(arena[indices[500]].0, 42);
+}
+
+#[test]
+fn persistent_arena_shares_unchanged_chunks() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Debug)]
+    struct Counted(usize, Rc<Cell<usize>>);
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            self.1.set(self.1.get() + 1);
+            Counted(self.0, self.1.clone())
+        }
+    }
+
+    let clones = Rc::new(Cell::new(0));
+    let v0: PersistentArena<_> = (0..1000).map(|i| Counted(i, clones.clone())).collect();
+    assert_eq!(clones.get(), 0);
+    let indices: Vec<_> = v0.iter().map(|(idx, _)| idx).collect();
+
+    // Only the chunk of the changed slot is copied.
+    let v1 = v0.update(indices[500], |value| value.0 = 42).unwrap();
+    let copied = clones.get();
+    assert!(copied > 0 && copied < 100);
+    let (v2, removed) = v1.remove(indices[0]).unwrap();
+    assert_eq!(removed.0, 0);
+    assert!(clones.get() - copied < 100);
+
+    // Versions are independent, and indices stay valid in descendants.
+    assert_eq!(v0.get(indices[500]).unwrap().0, 500);
+    assert_eq!(v1.get(indices[500]).unwrap().0, 42);
+    assert_eq!(v2.get(indices[500]).unwrap().0, 42);
+    assert!(v1.contains(indices[0]) && !v2.contains(indices[0]));
+    assert_eq!((v0.len(), v1.len(), v2.len()), (1000, 1000, 999));
+
+    // The freed slot is reused with a new generation.
+    let (v3, new) = v2.insert(Counted(1000, clones.clone()));
+    assert_eq!(slot(new), 0);
+    assert_ne!(new, indices[0]);
+    assert!(!v3.contains(indices[0]));
+    assert!(!v1.contains(new));
+}
+
+#[test]
+fn change_tracking() {
+    let mut arena = Arena::new();
+    let a = arena.insert("a");
+    let b = arena.insert("b");
+    let c = arena.insert("c");
+    assert!(arena.changes().is_none());
+    assert!(arena.checkpoint().is_none());
+
+    arena.set_track_changes(true);
+    arena[a] = "A";
+    arena.remove(b);
+    let d = arena.insert("d");
+    let e = arena.insert("e");
+    arena[d] = "D";
+    arena.remove(e);
+
+    let changes = arena.checkpoint().unwrap();
+    assert_eq!(changes.added(), &BTreeSet::from([d]));
+    assert_eq!(changes.modified(), &BTreeSet::from([a]));
+    assert_eq!(changes.removed(), &BTreeSet::from([b]));
+    assert!(arena.changes().unwrap().is_empty());
+
+    // Borrowing every element mutably counts as modifying it.
+    arena.retain(|idx, _| idx != c);
+    for _ in arena.iter_mut() {}
+    let changes = arena.checkpoint().unwrap();
+    assert_eq!(changes.modified(), &BTreeSet::from([a, d]));
+    assert_eq!(changes.removed(), &BTreeSet::from([c]));
+
+    // Rolling back a transaction rolls back its changes.
+    arena[a] = "a";
+    let before = arena.changes().unwrap().clone();
+    let mut tx = arena.begin();
+    tx.remove(a);
+    tx.insert("f");
+    tx.get_mut(d);
+    tx.rollback();
+    assert_eq!(arena.changes().unwrap(), &before);
+
+    let drained: BTreeSet<_> = arena.drain().map(|(idx, _)| idx).collect();
+    assert_eq!(arena.changes().unwrap().removed(), &drained);
+
+    arena.set_track_changes(false);
+    arena.insert("g");
+    assert!(arena.changes().is_none());
+}
+
+#[test]
+fn delta_only_holds_changed_slots() {
+    let mut arena = Arena::new();
+    let indices: Vec<_> = (0..1000).map(|i| arena.insert(i)).collect();
+    let mut replica = arena.clone();
+    replica.set_track_changes(true);
+
+    let old = arena.clone();
+    arena[indices[1]] = 42;
+    arena[indices[2]] = 2;
+    arena.remove(indices[3]);
+    let new = arena.insert(43);
+    let delta = Arena::diff(&old, &arena);
+    assert_eq!(delta.changed_slots(), 2);
+    assert_eq!(new.into_raw_parts().0, 3);
+
+    replica.apply_delta(delta).unwrap();
+    assert_eq!(replica[indices[1]], 42);
+    assert!(!replica.contains(indices[3]));
+    assert_eq!(replica[new], 43);
+    let changes = replica.checkpoint().unwrap();
+    assert_eq!(changes.added(), &BTreeSet::from([new]));
+    assert_eq!(changes.modified(), &BTreeSet::from([indices[1]]));
+    assert_eq!(changes.removed(), &BTreeSet::from([indices[3]]));
+
+    // Applying an empty delta changes nothing.
+    replica.apply_delta(Arena::diff(&arena, &arena)).unwrap();
+    assert!(replica.changes().unwrap().is_empty());
+}
+
+#[test]
+fn delta_only_applies_to_its_old_arena() {
+    let mut arena = Arena::with_capacity(2);
+    let a = arena.insert("a");
+    let old = arena.clone();
+    arena.insert("b");
+    arena.insert("c");
+    let delta = Arena::diff(&old, &arena);
+
+    let mut other = Arena::with_capacity(4);
+    other.insert("x");
+    assert_eq!(
+        other.apply_delta(delta.clone()),
+        Err(DeltaError::CapacityMismatch {
+            expected: 2,
+            found: 4
+        }),
+    );
+    assert_eq!(other.capacity(), 4);
+
+    let mut other = Arena::with_capacity(2);
+    assert_eq!(
+        other.apply_delta(delta.clone()),
+        Err(DeltaError::LenMismatch {
+            expected: 1,
+            found: 0
+        }),
+    );
+    assert!(other.is_empty());
+
+    let mut replica = old.clone();
+    assert_eq!(replica.apply_delta(delta), Ok(()));
+    assert_eq!(replica[a], "a");
+    assert_eq!(replica.len(), 3);
+}
+
+#[test]
+fn delta_leaves_out_pending_shared_reservations() {
+    let mut arena = Arena::with_capacity(1);
+    arena.insert(1);
+    let mut replica = arena.clone();
+    let old = arena.clone();
+    let idx = arena.reserver().reserve_index();
+    let delta = Arena::diff(&old, &arena);
+    assert_eq!(delta.changed_slots(), 0);
+
+    replica.apply_delta(delta).unwrap();
+    replica.flush_reserved();
+    assert_eq!(replica.capacity(), 1);
+    assert_eq!(arena.fill(idx, 2), Ok(()));
+}
+
+#[test]
+fn delta_keeps_the_free_slots_of_its_reuse_policy() {
+    let mut arena = Arena::<_, Index, Fifo>::with_capacity_and_key(4);
+    let indices: Vec<_> = (0..4).map(|i| arena.insert(i)).collect();
+    let mut replica = arena.clone();
+    let old = arena.clone();
+    for &i in &indices {
+        arena.remove(i);
+    }
+    replica.apply_delta(Arena::diff(&old, &arena)).unwrap();
+
+    // Reusing a slot pushes it back at the tail of the free list.
+    let i = replica.insert(4);
+    assert_eq!(arena.insert(4), i);
+    replica.remove(i);
+    arena.remove(i);
+    for i in 0..4 {
+        assert_eq!(replica.try_insert(i).ok(), Some(arena.insert(i)));
+    }
+    assert_eq!(replica.try_insert(4), Err(4));
+}
+
+#[test]
+fn observer_mirrors_insertions_and_removals() {
+    use generational_arena::observed::{ArenaObserver, ObservedArena};
+    use std::collections::BTreeMap;
+
+    #[derive(Default)]
+    struct Mirror {
+        elements: BTreeMap<Index, usize>,
+        clears: usize,
+    }
+
+    impl ArenaObserver<usize> for Mirror {
+        fn on_insert(&mut self, i: Index, value: &usize) {
+            assert!(self.elements.insert(i, *value).is_none());
+        }
+
+        fn on_remove(&mut self, i: Index, value: &usize) {
+            assert_eq!(self.elements.remove(&i), Some(*value));
+        }
+
+        fn on_clear(&mut self) {
+            self.elements.clear();
+            self.clears += 1;
+        }
+    }
+
+    fn assert_mirrored(arena: &ObservedArena<usize, Mirror>) {
+        let elements: BTreeMap<_, _> = arena.iter().map(|(i, &value)| (i, value)).collect();
+        assert_eq!(arena.observer().elements, elements);
+    }
+
+    let mut arena: Arena<usize> = (0..4).collect();
+    arena.remove(Index::from_raw_parts(1, 0));
+    let mut arena = ObservedArena::with_arena(arena, Mirror::default());
+    assert_mirrored(&arena);
+
+    let a = arena.insert(10);
+    arena.insert_with(|i| i.into_raw_parts().0 + 100);
+    assert_mirrored(&arena);
+
+    assert_eq!(arena.remove(a), Some(10));
+    assert_eq!(arena.remove(a), None);
+    assert_mirrored(&arena);
+
+    arena.retain(|_, value| *value % 2 == 0);
+    assert_mirrored(&arena);
+
+    arena.clear();
+    arena.insert(5);
+    assert_eq!(arena.drain().count(), 1);
+    assert_eq!(arena.observer().clears, 2);
+    assert_mirrored(&arena);
+}